@@ -1,25 +1,39 @@
-use std::fmt::Debug;
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Write as _};
 use std::io::{Result as IoResult, Write};
 
+use anyhow::Result as EResult;
+
 use crate::document::Document;
-use crate::errors::Result as EResult;
-use crate::text::Referenceable;
+use crate::errors::ErrorKind;
+use crate::text::{Referenceable, Text};
 
+pub mod bibliography;
 pub mod contents;
 pub mod control;
 pub mod gloss;
+pub mod group;
 pub mod heading;
+pub mod import;
+pub mod lang;
 pub mod list;
+pub mod pre;
+pub mod quote;
+pub mod raw;
 pub mod replacements;
+pub mod run;
 pub mod table;
 
+use bibliography::Bibliography;
+use contents::Contents;
 use control::DocumentControl;
 use gloss::Gloss;
+use group::Group;
 use heading::HeadingLike;
+use lang::LangScope;
 use replacements::Replacements;
 use table::Table;
 
-#[cfg(test)]
 use list::List;
 
 type OResult<T> = EResult<Option<T>>;
@@ -27,6 +41,35 @@ type OResult<T> = EResult<Option<T>>;
 #[derive(Debug, Default, Eq, PartialEq)]
 pub struct Parameter(pub Option<String>, pub String);
 
+impl fmt::Display for Parameter {
+    /// Writes the parameter as it would be written in a parameter list, with the name and value
+    /// surrounded by braces if they would otherwise be parsed differently.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(name) = &self.0 {
+            write_parameter_part(f, name)?;
+            write!(f, "=")?;
+        }
+        write_parameter_part(f, &self.1)
+    }
+}
+
+/// Writes the name or value of a parameter, surrounded by braces if it is empty, has leading or
+/// trailing whitespace, or contains characters with a special meaning in parameter lists.
+fn write_parameter_part(f: &mut fmt::Formatter, part: &str) -> fmt::Result {
+    let special = |c| matches!(c, ',' | '=' | '[' | ']' | '{' | '}' | '\\');
+    if !part.is_empty() && part.trim() == part && !part.contains(special) {
+        return write!(f, "{}", part);
+    }
+    write!(f, "{{")?;
+    for c in part.chars() {
+        if c == '}' || c == '\\' {
+            write!(f, "\\")?;
+        }
+        write!(f, "{}", c)?;
+    }
+    write!(f, "}}")
+}
+
 pub trait UpdateParam {
     /// Updates with the given parameter. If the parameter was not updated, returns the parameter.
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter>;
@@ -73,6 +116,10 @@ pub struct BlockCommon {
     pub class: String,
     pub id: String,
     pub start_line: usize,
+    /// The page break before the block when the document is printed, if any.
+    pub break_before: Option<PageBreak>,
+    /// The page break after the block when the document is printed, if any.
+    pub break_after: Option<PageBreak>,
 }
 
 impl BlockCommon {
@@ -82,6 +129,21 @@ impl BlockCommon {
             ..Default::default()
         }
     }
+
+    /// The classes of the block: the `class` parameter, followed by the classes of its page
+    /// breaks, such as `break-before-page`.
+    pub fn classes(&self) -> Cow<'_, str> {
+        if self.break_before.is_none() && self.break_after.is_none() {
+            return Cow::Borrowed(&self.class);
+        }
+        let mut classes = self.class.clone();
+        for (side, page_break) in [("before", self.break_before), ("after", self.break_after)] {
+            if let Some(page_break) = page_break {
+                write!(classes, " break-{}-{}", side, page_break.name()).unwrap();
+            }
+        }
+        Cow::Owned(classes)
+    }
 }
 
 impl UpdateParam for BlockCommon {
@@ -95,11 +157,55 @@ impl UpdateParam for BlockCommon {
                 self.id = param.1;
                 None
             }
+            Some("break-before") => {
+                self.break_before = Some(PageBreak::from_param(param)?);
+                None
+            }
+            Some("break-after") => {
+                self.break_after = Some(PageBreak::from_param(param)?);
+                None
+            }
             _ => Some(param),
         })
     }
 }
 
+/// A page break before or after a block when the document is printed, as given by the
+/// `break-before` and `break-after` parameters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PageBreak {
+    /// Always start a new page.
+    Page,
+    /// Start a new left-hand page.
+    Left,
+    /// Start a new right-hand page.
+    Right,
+    /// Avoid starting a new page.
+    Avoid,
+}
+
+impl PageBreak {
+    fn from_param(param: Parameter) -> EResult<PageBreak> {
+        Ok(match param.1.as_ref() {
+            "page" => PageBreak::Page,
+            "left" => PageBreak::Left,
+            "right" => PageBreak::Right,
+            "avoid" => PageBreak::Avoid,
+            _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+        })
+    }
+
+    /// The name of the page break, as used in its value and its class.
+    fn name(self) -> &'static str {
+        match self {
+            PageBreak::Page => "page",
+            PageBreak::Left => "left",
+            PageBreak::Right => "right",
+            PageBreak::Avoid => "avoid",
+        }
+    }
+}
+
 pub trait BlockType: Debug {
     /// Outputs the block.
     fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()>;
@@ -109,6 +215,12 @@ pub trait BlockType: Debug {
         Ok(Some(param))
     }
 
+    /// Calls `f` on each piece of text displayed in the body of the block.
+    fn visit_text(&self, _f: &mut dyn FnMut(&Text)) {}
+
+    /// Calls `f` on a mutable reference to each piece of text displayed in the body of the block.
+    fn visit_text_mut(&mut self, _f: &mut dyn FnMut(&mut Text)) {}
+
     /// Returns a `&dyn Referenceable` if the block can be referenced, otherwise returns `None`.
     fn as_referenceable(&self) -> Option<&dyn Referenceable> {
         None
@@ -129,11 +241,16 @@ pub trait BlockType: Debug {
         None
     }
 
-    #[cfg(test)]
+    /// Returns a `&List` if the block is a list, otherwise returns `None`.
     fn as_list(&self) -> Option<&List> {
         None
     }
 
+    /// Returns a `&mut List` if the block is a list, otherwise returns `None`.
+    fn as_mut_list(&mut self) -> Option<&mut List> {
+        None
+    }
+
     /// Returns a `&mut Table` if the block is a table, otherwise returns `None`.
     fn as_mut_table(&mut self) -> Option<&mut Table> {
         None
@@ -144,10 +261,36 @@ pub trait BlockType: Debug {
         None
     }
 
+    /// Returns a `&Bibliography` if the block is a bibliography, otherwise returns `None`.
+    fn as_bibliography(&self) -> Option<&Bibliography> {
+        None
+    }
+
+    /// Returns a `&mut Bibliography` if the block is a bibliography, otherwise returns `None`.
+    fn as_mut_bibliography(&mut self) -> Option<&mut Bibliography> {
+        None
+    }
+
+    /// Returns a `&Group` if the block is the start or end of a group, otherwise returns `None`.
+    fn as_group(&self) -> Option<&Group> {
+        None
+    }
+
+    /// Returns a `&LangScope` if the block is the start or end of a language scope, otherwise
+    /// returns `None`.
+    fn as_lang_scope(&self) -> Option<&LangScope> {
+        None
+    }
+
     /// Returns a `&DocumentControl` if the block is a document control block, otherwise returns `None`.
     fn as_control(&self) -> Option<&DocumentControl> {
         None
     }
+
+    /// Returns a `&Contents` if the block is a table of contents, otherwise returns `None`.
+    fn as_contents(&self) -> Option<&Contents> {
+        None
+    }
 }
 
 impl<T: BlockType> UpdateParam for T {
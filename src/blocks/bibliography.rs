@@ -0,0 +1,184 @@
+use std::io::{Result as IoResult, Write};
+
+use anyhow::Result as EResult;
+
+use crate::blocks::{BlockCommon, BlockType, Parameter, UpdateParam};
+use crate::document::Document;
+use crate::html;
+use crate::text::{ReferenceStyle, Referenceable, Text};
+
+type OResult<T> = EResult<Option<T>>;
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Bibliography {
+    pub title: Text,
+    pub entries: Vec<Entry>,
+    /// Whether citations of the entries are displayed as numbers rather than as author and year.
+    pub numbered: bool,
+    /// Whether the entries are sorted alphabetically by author and year.
+    pub sorted: bool,
+}
+
+impl Bibliography {
+    pub fn new() -> Bibliography {
+        Default::default()
+    }
+
+    /// Sorts the entries alphabetically by author, and then by year.
+    pub fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| a.author.cmp(&b.author).then_with(|| a.year.cmp(&b.year)));
+    }
+
+    fn tag(&self) -> &'static str {
+        if self.numbered {
+            "ol"
+        } else {
+            "ul"
+        }
+    }
+}
+
+impl BlockType for Bibliography {
+    fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
+        write!(w, "<div ")?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_class(
+            w,
+            &format!(
+                "{} {}",
+                document.default_class("bibliography"),
+                common.classes()
+            ),
+        )?;
+        write!(w, "><p")?;
+        document.write_class(w, document.default_class("bibliography-heading"))?;
+        write!(w, ">")?;
+        self.title.write_inline(w, document)?;
+        writeln!(w, "</p>")?;
+        writeln!(w, "<{}>", self.tag())?;
+        for entry in &self.entries {
+            entry.write(w, document)?;
+        }
+        writeln!(w, "</{}>", self.tag())?;
+        writeln!(w, "</div>\n")
+    }
+
+    fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        Ok(match param.0.as_ref() {
+            Some(_) => Some(param),
+            None => match param.1.as_ref() {
+                "numbered" => {
+                    self.numbered = true;
+                    None
+                }
+                "sort" => {
+                    self.sorted = true;
+                    None
+                }
+                _ => Some(param),
+            },
+        })
+    }
+
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.title);
+        for entry in &self.entries {
+            f(&entry.title);
+        }
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.title);
+        for entry in &mut self.entries {
+            f(&mut entry.title);
+        }
+    }
+
+    fn as_bibliography(&self) -> Option<&Bibliography> {
+        Some(self)
+    }
+
+    fn as_mut_bibliography(&mut self) -> Option<&mut Bibliography> {
+        Some(self)
+    }
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Entry {
+    pub id: String,
+    pub author: String,
+    pub year: String,
+    pub title: Text,
+    /// The number of the entry, if the bibliography is numbered.
+    pub number: Option<usize>,
+}
+
+impl Entry {
+    pub fn new() -> Entry {
+        Default::default()
+    }
+
+    fn write(&self, w: &mut dyn Write, document: &Document) -> IoResult<()> {
+        if self.id.is_empty() {
+            write!(w, "<li>")?;
+        } else {
+            write!(w, "<li id=\"{}\">", html::Encoder(&self.id))?;
+        }
+        if !self.author.is_empty() {
+            write!(w, "<span")?;
+            document.write_class(w, document.default_class("bibliography-author"))?;
+            write!(w, ">{}</span> ", html::Encoder(&self.author))?;
+        }
+        if !self.year.is_empty() {
+            write!(w, "(<span")?;
+            document.write_class(w, document.default_class("bibliography-year"))?;
+            write!(w, ">{}</span>). ", html::Encoder(&self.year))?;
+        }
+        write!(w, "<cite>")?;
+        self.title.write_inline(w, document)?;
+        writeln!(w, "</cite></li>")
+    }
+}
+
+impl UpdateParam for Entry {
+    fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("id") | None => {
+                self.id = param.1;
+                None
+            }
+            Some("author") => {
+                self.author = param.1;
+                None
+            }
+            Some("year") => {
+                self.year = param.1;
+                None
+            }
+            Some(_) => Some(param),
+        })
+    }
+}
+
+impl Referenceable for Entry {
+    fn reference_text(&self, _document: &Document) -> Text {
+        if self.author.is_empty() && self.year.is_empty() {
+            self.title.clone()
+        } else if self.year.is_empty() {
+            Text::from(self.author.as_str())
+        } else if self.author.is_empty() {
+            Text::from(self.year.as_str())
+        } else {
+            Text::from(format!("{} {}", self.author, self.year))
+        }
+    }
+
+    fn reference_number(&self, _document: &Document) -> Option<String> {
+        self.number.map(|number| format!("{}", number))
+    }
+
+    fn default_reference_style(&self) -> ReferenceStyle {
+        ReferenceStyle::Citation
+    }
+}
@@ -1,10 +1,11 @@
-use std::io::{Result as IoResult, Write};
+use std::io::{self, Result as IoResult, Write};
 
-use anyhow::Context;
+use anyhow::Result as EResult;
+use itertools::Itertools;
 
 use crate::blocks::{BlockCommon, BlockType, Parameter};
 use crate::document::Document;
-use crate::errors::{ErrorKind, Result as EResult};
+use crate::errors::{Context, ErrorKind};
 use crate::html;
 use crate::text::Text;
 
@@ -14,6 +15,18 @@ type OResult<T> = EResult<Option<T>>;
 pub struct Contents {
     pub title: Text,
     pub max_level: usize,
+    /// The ID of the heading whose subsections are listed, or `None` to list all sections.
+    pub root: Option<String>,
+    /// Whether each link has a `data-section` attribute containing the ID of its section, for
+    /// scripts which mark the current section.
+    pub data_links: bool,
+    /// Whether the section number is displayed before the title of each section.
+    pub numbers: bool,
+    /// Whether the ID of each section is displayed after its title, as a marker of where the
+    /// section can be linked to.
+    pub anchors: bool,
+    /// Whether the title of the document is included as an entry containing the sections.
+    pub document_title: bool,
 }
 
 impl Contents {
@@ -40,7 +53,9 @@ impl Contents {
             for &e in list {
                 let heading = document.get_heading(e);
                 if !heading.numbered() {
-                    write!(w, r#"<li class="nonumber">"#)?;
+                    write!(w, "<li")?;
+                    document.write_class(w, document.default_class("nonumber"))?;
+                    write!(w, ">")?;
                     manual_number = true;
                 } else if manual_number {
                     write!(w, r#"<li value="{}">"#, heading.number().last().unwrap())?;
@@ -49,13 +64,9 @@ impl Contents {
                     write!(w, "<li>")?;
                 }
                 if heading.toc() {
-                    write!(
-                        w,
-                        "<a href=\"#{}\">",
-                        &document.get_block(e).unwrap().common.id
-                    )?;
-                    heading.title().write_inline(w, document)?;
-                    write!(w, "</a>")?;
+                    let id = &document.get_block(e).unwrap().common.id;
+                    let number = Some(heading.number()).filter(|_| heading.numbered());
+                    self.write_link(w, id, number, heading.title(), document)?;
                 }
                 self.write_sublist(w, level + 1, heading.children(), document)?;
                 writeln!(w, "</li>")?;
@@ -64,18 +75,88 @@ impl Contents {
         }
         Ok(())
     }
+
+    /// Writes the link to the section with the given ID and title.
+    fn write_link(
+        &self,
+        w: &mut dyn Write,
+        id: &str,
+        number: Option<&[usize]>,
+        title: &Text,
+        document: &Document,
+    ) -> IoResult<()> {
+        write!(w, "<a href=\"{}\"", html::Encoder(&document.href(id)))?;
+        if self.data_links {
+            write!(w, " data-section=\"{}\"", html::Encoder(id))?;
+        }
+        if document.active_section().map(String::as_str) == Some(id) {
+            write!(w, " aria-current=\"location\"")?;
+        }
+        write!(w, ">")?;
+        if let Some(number) = number.filter(|_| self.numbers) {
+            write!(w, "<span")?;
+            document.write_class(w, document.default_class("toc-number"))?;
+            write!(w, ">{}</span> ", number.iter().format("."))?;
+        }
+        title.write_inline(w, document)?;
+        write!(w, "</a>")?;
+        if self.anchors {
+            write!(w, " <span")?;
+            document.write_class(w, document.default_class("toc-anchor"))?;
+            write!(w, ">#{}</span>", html::Encoder(id))?;
+        }
+        Ok(())
+    }
 }
 
 impl BlockType for Contents {
     fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
-        write!(w, "<div ")?;
-        write!(w, "id=\"{}\" ", html::Encoder(&common.id))?;
-        write!(w, "class=\"{} toc\">", html::Encoder(&common.class))?;
-        write!(w, "<p class=\"toc-heading\">")?;
+        write!(w, "<nav ")?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_class(
+            w,
+            &format!("{} {}", common.classes(), document.default_class("toc")),
+        )?;
+        write!(w, " aria-label=\"")?;
+        document
+            .aria_label("toc", "Table of contents")
+            .write_inline_plain(w, document)?;
+        write!(w, "\"><p")?;
+        document.write_class(w, document.default_class("toc-heading"))?;
+        write!(w, ">")?;
         self.title.write_inline(w, document)?;
         writeln!(w, "</p>")?;
-        self.write_sublist(w, 1, document.get_section_list(None), document)?;
-        writeln!(w, "</div>\n")
+        let sections = match &self.root {
+            Some(root) => match document.get_index(root) {
+                Some(idx) if document.get_block(idx).unwrap().kind.as_heading().is_some() => {
+                    document.get_section_list(Some(idx))
+                }
+                _ => return Err(io::Error::other(ErrorKind::TocRoot(root.clone()))),
+            },
+            None => document.get_section_list(None),
+        };
+        match (document.title(), document.title_id()) {
+            (Some(title), Some(title_id)) if self.document_title && self.root.is_none() => {
+                writeln!(w, "<ol>")?;
+                write!(w, "<li")?;
+                document.write_class(w, document.default_class("nonumber"))?;
+                write!(w, ">")?;
+                self.write_link(w, title_id, None, title, document)?;
+                self.write_sublist(w, sections.level, sections, document)?;
+                writeln!(w, "</li>")?;
+                writeln!(w, "</ol>\n")?;
+            }
+            _ => self.write_sublist(w, sections.level, sections, document)?,
+        }
+        writeln!(w, "</nav>\n")
+    }
+
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.title);
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.title);
     }
 
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
@@ -84,9 +165,33 @@ impl BlockType for Contents {
                 self.max_level = param.1.parse::<usize>().context(ErrorKind::Parse)?;
                 None
             }
+            Some("root") => {
+                self.root = Some(param.1);
+                None
+            }
+            None if param.1 == "data" => {
+                self.data_links = true;
+                None
+            }
+            None if param.1 == "numbers" => {
+                self.numbers = true;
+                None
+            }
+            None if param.1 == "anchors" => {
+                self.anchors = true;
+                None
+            }
+            None if param.1 == "title" => {
+                self.document_title = true;
+                None
+            }
             _ => Some(param),
         })
     }
+
+    fn as_contents(&self) -> Option<&Contents> {
+        Some(self)
+    }
 }
 
 impl Default for Contents {
@@ -94,6 +199,11 @@ impl Default for Contents {
         Contents {
             title: Text::from("Table of Contents"),
             max_level: 6,
+            root: None,
+            data_links: false,
+            numbers: false,
+            anchors: false,
+            document_title: false,
         }
     }
 }
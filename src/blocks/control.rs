@@ -7,11 +7,176 @@ use crate::text::Text;
 #[derive(Debug, Eq, PartialEq)]
 pub enum DocumentControl {
     Title(Text),
+    /// Use the title of the first section as the title, if no title is given.
+    AutoTitle,
+    /// Number tables and glosses with a single counter, in document order.
+    SharedNumbers,
     Stylesheet(Text),
     Author(Text),
     Description(Text),
     Lang(Text),
     Import(Text),
+    ReferencePrefixes(Vec<(String, Text)>),
+    IdPrefixes(Vec<(String, Text)>),
+    AriaLabels(Vec<(String, Text)>),
+    NumberStyles(Vec<(String, NumberStyle)>),
+    Abbreviations(Vec<(String, Text)>),
+    Undefined(Undefined),
+    Formatting(Formatting),
+    Doctype(String),
+    Theme(String),
+    MaxHeading(MaxHeading),
+    /// The base URL prepended to relative URLs of links and stylesheets.
+    BaseUrl(String),
+    HtmlAttributes(Vec<(String, Text)>),
+    /// The separator between variant forms, as in "katu ~ kado".
+    VariantSeparator(String),
+    /// How blocks which would otherwise be paragraphs are parsed.
+    DefaultBlock(DefaultBlock),
+    /// Sets a variable, for use in `:if:` blocks.
+    Define(String),
+    /// Starts a section of the document which is only included if the variable is set, or, if
+    /// `negated` is true, if it isn't.
+    If {
+        variable: String,
+        negated: bool,
+    },
+    /// Ends the section started by the last `:if:` block.
+    EndIf,
+    /// Where a table of contents is generated automatically.
+    AutoToc(AutoToc),
+}
+
+/// Where a table of contents is generated automatically, regardless of the `:toc:` blocks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AutoToc {
+    /// At the start of the document, after its title.
+    AfterTitle,
+    /// Nowhere; the `:toc:` blocks are omitted as well.
+    None,
+}
+
+/// How blocks which aren't headings or block directives are parsed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DefaultBlock {
+    /// As paragraphs of text.
+    #[default]
+    Paragraph,
+    /// As raw lines, which are written to the output unchanged.
+    Raw,
+}
+
+/// How to display references to undefined IDs and undefined replacements.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Undefined {
+    /// A `<span>` with the class `undefined-reference` or `undefined-replace`.
+    #[default]
+    Span,
+    /// Plain text.
+    Text,
+    /// Raise an error.
+    Error,
+}
+
+/// How emphasis, strong emphasis, italics, and bold are written.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Formatting {
+    /// The native elements: `<em>`, `<strong>`, `<i>`, and `<b>`.
+    #[default]
+    Tags,
+    /// `<span>` elements with the classes `emphasis`, `strong`, `italics`, and `bold`.
+    Classes,
+}
+
+/// The deepest level of heading displayed as a heading; deeper headings are displayed as bold
+/// paragraphs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MaxHeading {
+    pub level: usize,
+    /// Whether the deeper headings are displayed with their section numbers.
+    pub numbered: bool,
+}
+
+/// How the numbers of a kind of block are displayed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NumberStyle {
+    /// `1`, `2`, `3`, ...
+    #[default]
+    Arabic,
+    /// `i`, `ii`, `iii`, ...
+    LowerRoman,
+    /// `I`, `II`, `III`, ...
+    UpperRoman,
+    /// `a`, `b`, ..., `z`, `aa`, `ab`, ...
+    LowerAlpha,
+    /// `A`, `B`, ..., `Z`, `AA`, `AB`, ...
+    UpperAlpha,
+}
+
+impl NumberStyle {
+    /// Parses a number style from its name, which is the same as the corresponding value of the
+    /// CSS `list-style-type` property.
+    pub fn from_name(name: &str) -> Option<NumberStyle> {
+        Some(match name {
+            "arabic" | "decimal" => NumberStyle::Arabic,
+            "lower-roman" => NumberStyle::LowerRoman,
+            "upper-roman" => NumberStyle::UpperRoman,
+            "lower-alpha" => NumberStyle::LowerAlpha,
+            "upper-alpha" => NumberStyle::UpperAlpha,
+            _ => return None,
+        })
+    }
+
+    /// Formats the number in this style. Zero is always formatted as an arabic numeral, since it
+    /// has no roman numeral or letter.
+    pub fn format(self, number: usize) -> String {
+        match self {
+            _ if number == 0 => "0".into(),
+            NumberStyle::Arabic => format!("{}", number),
+            NumberStyle::LowerRoman => roman(number).to_lowercase(),
+            NumberStyle::UpperRoman => roman(number),
+            NumberStyle::LowerAlpha => alpha(number).to_lowercase(),
+            NumberStyle::UpperAlpha => alpha(number),
+        }
+    }
+}
+
+/// Formats a positive number as an uppercase roman numeral.
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            out.push_str(numeral);
+            number -= value;
+        }
+    }
+    out
+}
+
+/// Formats a positive number as uppercase letters, continuing after `Z` with `AA`.
+fn alpha(mut number: usize) -> String {
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push(char::from(b'A' + (number % 26) as u8));
+        number /= 26;
+    }
+    letters.iter().rev().collect()
 }
 
 impl BlockType for DocumentControl {
@@ -2,7 +2,9 @@ use std::io::{Result as IoResult, Write};
 
 use crate::blocks::{BlockCommon, BlockType, Parameter};
 use crate::document::Document;
-use crate::errors::Result as EResult;
+use anyhow::Result as EResult;
+
+use crate::errors::{Context, ErrorKind};
 use crate::html;
 use crate::text::{Referenceable, Text};
 
@@ -13,58 +15,308 @@ pub struct Gloss {
     pub title: Text,
     pub numbered: bool,
     pub number: usize,
+    /// A number specified by the `number` parameter, to use instead of the automatic number.
+    pub explicit_number: Option<usize>,
     pub preamble: Vec<Text>,
     pub gloss: Vec<GlossLine>,
+    /// The lines of the `translation` tier, displayed after the gloss lines and before the
+    /// postamble.
+    pub translation: Vec<Text>,
     pub postamble: Vec<Text>,
+    /// The attribution of the example, such as the speaker or the work it is taken from,
+    /// displayed at the end of the gloss.
+    pub attribution: Option<Text>,
+    /// The text to fill in for words missing from a gloss line.
+    pub empty_cell: String,
+    /// How the heading of the gloss is displayed.
+    pub style: GlossStyle,
+    /// Whether the gloss is displayed on a single line, rather than as an interlinear gloss.
+    pub compact: bool,
+    /// The class of the element separating words, or `None` to separate them with plain spaces.
+    pub gap: Option<String>,
+    /// The columns (counting from 1) whose words are highlighted.
+    pub highlight: Vec<usize>,
+}
+
+/// How the heading of a gloss is displayed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum GlossStyle {
+    /// A heading like "Gloss 3:", followed by the title.
+    #[default]
+    Heading,
+    /// The number in parentheses, like "(3)", followed by the title, as in linguistics papers.
+    Example,
 }
 
 impl Gloss {
     pub fn new() -> Gloss {
         Default::default()
     }
+
+    /// Writes the opening tag of the gloss, with the given name.
+    fn write_open(
+        &self,
+        w: &mut dyn Write,
+        tag: &str,
+        common: &BlockCommon,
+        document: &Document,
+    ) -> IoResult<()> {
+        write!(w, "<{} ", tag)?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_target_attributes(w)?;
+        let mut class = format!("{} ", document.default_class("gloss"));
+        if self.style == GlossStyle::Example {
+            class.push_str(document.default_class("example"));
+            class.push(' ');
+        }
+        if self.compact {
+            class.push_str(document.default_class("compact"));
+            class.push(' ');
+        }
+        class.push_str(&common.classes());
+        if let Some(theme) = document.theme_class() {
+            class.push(' ');
+            class.push_str(&theme);
+        }
+        document.write_class(w, &class)?;
+        write!(w, " role=\"figure\" aria-label=\"")?;
+        document
+            .aria_label("gloss", "Gloss")
+            .write_inline_plain(w, document)?;
+        if self.numbered {
+            write!(w, " {}", document.format_number("gloss", self.number))?;
+        }
+        write!(w, "\">")
+    }
+
+    /// Writes the number of the gloss, as specified by its style.
+    fn write_label(&self, w: &mut dyn Write, document: &Document) -> IoResult<()> {
+        match self.style {
+            GlossStyle::Heading => {
+                write!(w, "<span")?;
+                document.write_class(w, document.default_class("gloss-heading-prefix"))?;
+                write!(w, ">Gloss")?;
+                if self.numbered {
+                    write!(w, " {}", document.format_number("gloss", self.number))?;
+                }
+                write!(w, ":</span> ")
+            }
+            GlossStyle::Example => {
+                if self.numbered {
+                    write!(w, "<span")?;
+                    document.write_class(w, document.default_class("gloss-number"))?;
+                    write!(
+                        w,
+                        ">({})</span> ",
+                        document.format_number("gloss", self.number)
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the gloss on a single line, with the first gloss line followed by each of the
+    /// others in parentheses.
+    fn write_compact(
+        &self,
+        w: &mut dyn Write,
+        common: &BlockCommon,
+        document: &Document,
+    ) -> IoResult<()> {
+        self.write_open(w, "p", common, document)?;
+        self.write_label(w, document)?;
+        self.title.write_inline(w, document)?;
+        // the label ends with a space, so only a title needs one after it
+        let mut space = !self.title.0.is_empty();
+        let mut separate = |w: &mut dyn Write| -> IoResult<()> {
+            if space {
+                write!(w, " ")?;
+            }
+            space = true;
+            Ok(())
+        };
+        for line in &self.preamble {
+            separate(w)?;
+            write!(w, "<span")?;
+            document.write_class(w, document.default_class("preamble"))?;
+            write!(w, ">")?;
+            line.write_inline(w, document)?;
+            write!(w, "</span>")?;
+        }
+        for (i, line) in self.lines().enumerate() {
+            separate(w)?;
+            if i > 0 {
+                write!(w, "(")?;
+            }
+            write!(w, "<span")?;
+            document.write_class(w, &line.class(document))?;
+            write!(w, ">")?;
+            line.write_words(w, self.gap.as_deref(), &self.highlight, document)?;
+            write!(w, "</span>")?;
+            if i > 0 {
+                write!(w, ")")?;
+            }
+        }
+        for line in &self.translation {
+            separate(w)?;
+            write!(w, "<span")?;
+            document.write_class(w, document.default_class("translation"))?;
+            write!(w, ">")?;
+            line.write_inline(w, document)?;
+            write!(w, "</span>")?;
+        }
+        for line in &self.postamble {
+            separate(w)?;
+            write!(w, "<span")?;
+            document.write_class(w, document.default_class("postamble"))?;
+            write!(w, ">")?;
+            line.write_inline(w, document)?;
+            write!(w, "</span>")?;
+        }
+        if let Some(attribution) = &self.attribution {
+            separate(w)?;
+            self.write_attribution(w, attribution, document)?;
+        }
+        writeln!(w, "</p>\n")
+    }
+
+    /// Writes the attribution of the gloss.
+    fn write_attribution(
+        &self,
+        w: &mut dyn Write,
+        attribution: &Text,
+        document: &Document,
+    ) -> IoResult<()> {
+        write!(w, "<cite")?;
+        document.write_class(w, document.default_class("gloss-source"))?;
+        write!(w, ">")?;
+        attribution.write_inline(w, document)?;
+        write!(w, "</cite>")
+    }
+
+    /// The number of columns of the gloss, which is the number of words in the longest line which
+    /// isn't a tooltip.
+    pub fn columns(&self) -> usize {
+        self.lines().map(|line| line.words.len()).max().unwrap_or(0)
+    }
+
+    /// The gloss lines which aren't tooltips, with the lines with tiers first, in the order of
+    /// their tiers, and then the others in the order they were written.
+    fn lines(&self) -> impl Iterator<Item = &GlossLine> {
+        let mut lines = self
+            .gloss
+            .iter()
+            .filter(|line| !line.tooltip)
+            .collect::<Vec<_>>();
+        lines.sort_by_key(|line| line.tier);
+        lines.into_iter()
+    }
+}
+
+/// Gets the class of a word of the given line, which includes the `highlight` class if the word is
+/// highlighted.
+fn highlight_class(line: &GlossLine, highlighted: bool, document: &Document) -> String {
+    if highlighted {
+        format!(
+            "{} {}",
+            document.default_class("highlight"),
+            line.class(document)
+        )
+    } else {
+        line.class(document)
+    }
+}
+
+/// Writes the space between two words of a gloss, in an element with the given class, if there
+/// is one.
+fn write_gap(w: &mut dyn Write, gap: Option<&str>) -> IoResult<()> {
+    match gap {
+        Some(class) => write!(w, "<span class=\"{}\"> </span>", html::Encoder(class)),
+        None => write!(w, " "),
+    }
 }
 
 impl BlockType for Gloss {
     fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
-        write!(w, "<div ")?;
-        write!(w, "id=\"{}\" ", html::Encoder(&common.id))?;
-        write!(w, "class=\"gloss {}\">", html::Encoder(&common.class))?;
-        write!(w, "<p class=\"gloss-heading\">")?;
-        write!(w, "<span class=\"gloss-heading-prefix\">Gloss")?;
-        if self.numbered {
-            write!(w, " {}", self.number)?;
+        if self.compact {
+            return self.write_compact(w, common, document);
         }
-        write!(w, ":</span> ")?;
+        self.write_open(w, "div", common, document)?;
+        write!(w, "<p")?;
+        document.write_class(w, document.default_class("gloss-heading"))?;
+        write!(w, ">")?;
+        self.write_label(w, document)?;
         self.title.write_inline(w, document)?;
         writeln!(w, "</p>")?;
         for line in &self.preamble {
-            write!(w, r#"<p class="preamble">"#)?;
+            write!(w, "<p")?;
+            document.write_class(w, document.default_class("preamble"))?;
+            write!(w, ">")?;
             line.write_inline(w, document)?;
             writeln!(w, "</p>")?;
         }
-        // get the length of the longest gloss line. If there are no lines, skip writing the gloss
-        if let Some(num_words) = self.gloss.iter().map(|line| line.words.len()).max() {
+        let tooltips = self
+            .gloss
+            .iter()
+            .filter(|line| line.tooltip)
+            .collect::<Vec<_>>();
+        let lines = self.lines().collect::<Vec<_>>();
+        if let ([line], []) = (&lines[..], &tooltips[..]) {
+            // a gloss with a single line has nothing to align, so it is written as a paragraph
+            write!(w, "<p")?;
+            document.write_class(w, &line.class(document))?;
+            write!(w, ">")?;
+            line.write_words(w, self.gap.as_deref(), &self.highlight, document)?;
+            writeln!(w, "</p>")?;
+        } else if let Some(num_words) = lines.iter().map(|line| line.words.len()).max() {
+            // `num_words` is the length of the longest gloss line. If there are no lines, the gloss
+            // isn't written.
             // flag whether to add a space before the next word.
             let mut add_space = false;
             for i in 0..num_words {
-                let head_word = self.gloss[0].words.get(i);
+                let head_word = lines[0].words.get(i);
                 let is_prefix = match head_word {
                     Some(word) => word.starts_with('-'),
                     None => false,
                 };
                 if add_space || !is_prefix {
-                    write!(w, " ")?;
+                    write_gap(w, self.gap.as_deref())?;
                 }
+                let highlighted = self.highlight.contains(&(i + 1));
                 write!(w, "<dl>")?;
-                write!(w, "<dt class=\"{}\">", html::Encoder(&self.gloss[0].class))?;
+                write!(w, "<dt")?;
+                document.write_class(w, &highlight_class(lines[0], highlighted, document))?;
+                let mut notes = tooltips
+                    .iter()
+                    .filter_map(|line| line.words.get(i))
+                    .peekable();
+                if notes.peek().is_some() {
+                    // each tooltip line is on its own line of the tooltip
+                    write!(w, " title=\"")?;
+                    for (j, note) in notes.enumerate() {
+                        if j > 0 {
+                            writeln!(w)?;
+                        }
+                        note.write_inline_plain(w, document)?;
+                    }
+                    write!(w, "\"")?;
+                }
+                write!(w, ">")?;
                 if let Some(text) = head_word {
                     text.write_inline(w, document)?;
+                } else {
+                    write!(w, "{}", html::Encoder(&self.empty_cell))?;
                 }
                 write!(w, "</dt>")?;
-                for line in &self.gloss[1..] {
-                    write!(w, "<dd class=\"{}\">", html::Encoder(&line.class))?;
+                for line in &lines[1..] {
+                    write!(w, "<dd")?;
+                    document.write_class(w, &highlight_class(line, highlighted, document))?;
+                    write!(w, ">")?;
                     if let Some(text) = line.words.get(i) {
                         text.write_inline(w, document)?;
+                    } else {
+                        write!(w, "{}", html::Encoder(&self.empty_cell))?;
                     }
                     write!(w, "</dd>")?;
                 }
@@ -75,28 +327,114 @@ impl BlockType for Gloss {
                 };
             }
         }
+        for line in &self.translation {
+            write!(w, "<p")?;
+            document.write_class(w, document.default_class("translation"))?;
+            write!(w, ">")?;
+            line.write_inline(w, document)?;
+            writeln!(w, "</p>")?;
+        }
         for line in &self.postamble {
-            write!(w, r#"<p class="postamble">"#)?;
+            write!(w, "<p")?;
+            document.write_class(w, document.default_class("postamble"))?;
+            write!(w, ">")?;
             line.write_inline(w, document)?;
             writeln!(w, "</p>")?;
         }
+        if let Some(attribution) = &self.attribution {
+            write!(w, "<p>")?;
+            self.write_attribution(w, attribution, document)?;
+            writeln!(w, "</p>")?;
+        }
         writeln!(w, "</div>\n")?;
         Ok(())
     }
 
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
-        Ok(match param.0.as_ref() {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("emptycell") => {
+                self.empty_cell = param.1;
+                None
+            }
+            Some("number") => {
+                let number = param.1.parse::<usize>().context(ErrorKind::Parse)?;
+                if number == 0 {
+                    // numbering starts from 1
+                    return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into());
+                }
+                self.explicit_number = Some(number);
+                None
+            }
+            Some("gap") => {
+                self.gap = Some(param.1);
+                None
+            }
+            Some("highlight") => {
+                self.highlight
+                    .push(param.1.parse::<usize>().context(ErrorKind::Parse)?);
+                None
+            }
+            Some("style") => {
+                self.style = match param.1.as_ref() {
+                    "heading" => GlossStyle::Heading,
+                    "example" => GlossStyle::Example,
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                };
+                None
+            }
             Some(_) => Some(param),
             None => match param.1.as_ref() {
                 "nonumber" => {
                     self.numbered = false;
                     None
                 }
+                "compact" => {
+                    self.compact = true;
+                    None
+                }
                 _ => Some(param),
             },
         })
     }
 
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.title);
+        for line in &self.preamble {
+            f(line);
+        }
+        for word in self.gloss.iter().flat_map(|line| &line.words) {
+            f(word);
+        }
+        for line in &self.translation {
+            f(line);
+        }
+        for line in &self.postamble {
+            f(line);
+        }
+        if let Some(attribution) = &self.attribution {
+            f(attribution);
+        }
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.title);
+        for line in &mut self.preamble {
+            f(line);
+        }
+        for word in self.gloss.iter_mut().flat_map(|line| &mut line.words) {
+            f(word);
+        }
+        for line in &mut self.translation {
+            f(line);
+        }
+        for line in &mut self.postamble {
+            f(line);
+        }
+        if let Some(attribution) = &mut self.attribution {
+            f(attribution);
+        }
+    }
+
     fn as_mut_gloss(&mut self) -> Option<&mut Gloss> {
         Some(self)
     }
@@ -107,15 +445,23 @@ impl BlockType for Gloss {
 }
 
 impl Referenceable for Gloss {
-    fn reference_text(&self) -> Text {
-        let mut text = Text::from("gloss ");
+    fn reference_text(&self, document: &Document) -> Text {
+        let mut text = document.reference_prefix("gloss", "gloss ");
         if self.numbered {
-            text.push(format!("{}", self.number));
+            text.push(document.format_number("gloss", self.number));
         } else {
             text.extend(&self.title);
         }
         text
     }
+
+    fn reference_number(&self, document: &Document) -> Option<String> {
+        if self.numbered {
+            Some(document.format_number("gloss", self.number))
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for Gloss {
@@ -124,9 +470,17 @@ impl Default for Gloss {
             title: Default::default(),
             numbered: true,
             number: 0,
+            explicit_number: None,
             preamble: Default::default(),
             gloss: Default::default(),
+            translation: Default::default(),
             postamble: Default::default(),
+            attribution: None,
+            empty_cell: Default::default(),
+            style: Default::default(),
+            compact: false,
+            gap: None,
+            highlight: Vec::new(),
         }
     }
 }
@@ -135,6 +489,11 @@ impl Default for Gloss {
 pub struct GlossLine {
     pub words: Vec<Text>,
     pub class: String,
+    /// Whether the words are displayed as tooltips of the aligned words of the first line, rather
+    /// than as a line of their own.
+    pub tooltip: bool,
+    /// The tier of the line, which determines the order the lines are displayed in.
+    pub tier: GlossTier,
 }
 
 impl GlossLine {
@@ -142,16 +501,102 @@ impl GlossLine {
         Default::default()
     }
 
+    /// Writes the words of the line, separated by spaces except at affix boundaries, with the
+    /// words in the `highlight` columns highlighted.
+    fn write_words(
+        &self,
+        w: &mut dyn Write,
+        gap: Option<&str>,
+        highlight: &[usize],
+        document: &Document,
+    ) -> IoResult<()> {
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 && !self.words[i - 1].ends_with('-') && !word.starts_with('-') {
+                write_gap(w, gap)?;
+            }
+            if highlight.contains(&(i + 1)) {
+                write!(w, "<span")?;
+                document.write_class(w, document.default_class("highlight"))?;
+                write!(w, ">")?;
+                word.write_inline(w, document)?;
+                write!(w, "</span>")?;
+            } else {
+                word.write_inline(w, document)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the class of the line, which includes a class for its tier, if it has one.
+    fn class(&self, document: &Document) -> String {
+        match self.tier.class() {
+            Some(class) => format!("{} {}", document.default_class(class), self.class),
+            None => self.class.clone(),
+        }
+    }
+
     pub fn push(&mut self, word: Text) {
         self.words.push(word);
     }
 }
 
+/// A tier of an interlinear gloss, specified by the `tier` parameter of a gloss line. Lines are
+/// displayed in the order of their tiers, regardless of the order they are written in.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum GlossTier {
+    /// The text in the orthography of the language.
+    Orthography,
+    /// The transcription of the text in IPA.
+    Ipa,
+    /// The morpheme-by-morpheme gloss.
+    Gloss,
+    /// The free translation, which isn't split into words, and is displayed after the other
+    /// lines.
+    Translation,
+    /// A line without a tier, displayed after the lines with tiers.
+    #[default]
+    Untiered,
+}
+
+impl GlossTier {
+    /// The class of lines of the tier.
+    fn class(self) -> Option<&'static str> {
+        match self {
+            GlossTier::Orthography => Some("tier-orthography"),
+            GlossTier::Ipa => Some("tier-ipa"),
+            GlossTier::Gloss => Some("tier-gloss"),
+            GlossTier::Translation => Some("translation"),
+            GlossTier::Untiered => None,
+        }
+    }
+
+    /// Updates with the given parameter. If the parameter was not updated, returns the parameter.
+    pub fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("tier") => {
+                *self = match param.1.as_ref() {
+                    "orthography" => GlossTier::Orthography,
+                    "ipa" => GlossTier::Ipa,
+                    "gloss" => GlossTier::Gloss,
+                    "translation" => GlossTier::Translation,
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                };
+                None
+            }
+            _ => Some(param),
+        })
+    }
+}
+
 #[derive(Debug, Default, Eq, PartialEq)]
 pub enum GlossLineType {
     NoSplit,
     #[default]
     Split,
+    /// A line whose words are displayed as tooltips of the aligned words of the first line.
+    Tooltip,
+    /// A line giving the attribution of the example, which isn't split into words.
+    Attribution,
 }
 
 impl GlossLineType {
@@ -164,6 +609,14 @@ impl GlossLineType {
                     *self = GlossLineType::NoSplit;
                     None
                 }
+                "tooltip" => {
+                    *self = GlossLineType::Tooltip;
+                    None
+                }
+                "attribution" => {
+                    *self = GlossLineType::Attribution;
+                    None
+                }
                 _ => Some(param),
             },
         })
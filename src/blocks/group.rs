@@ -0,0 +1,89 @@
+use std::io::{Result as IoResult, Write};
+
+use anyhow::Result as EResult;
+
+use crate::blocks::{BlockCommon, BlockType, Parameter};
+use crate::document::Document;
+use crate::errors::ErrorKind;
+use crate::html;
+
+type OResult<T> = EResult<Option<T>>;
+
+/// The start or end of a group of consecutive blocks, which are wrapped in a `<div>` with the
+/// group's attributes.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Group {
+    Start(GroupStart),
+    End {
+        /// Whether this ends a multi-column layout (`:endcolumns:`), rather than a group
+        /// (`:endgroup:`).
+        columns: bool,
+    },
+}
+
+/// The attributes of a group.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct GroupStart {
+    /// The `lang` attribute of the group.
+    pub lang: Option<String>,
+    /// The `dir` attribute of the group.
+    pub dir: Option<String>,
+    /// The number of columns the blocks of the group are laid out in, if the group is a
+    /// multi-column layout.
+    pub columns: Option<usize>,
+}
+
+impl BlockType for Group {
+    fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
+        match self {
+            Group::Start(start) => {
+                write!(w, "<div ")?;
+                write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+                let kind = if start.columns.is_some() {
+                    "columns"
+                } else {
+                    "group"
+                };
+                document.write_class(
+                    w,
+                    &format!("{} {}", document.default_class(kind), common.classes()),
+                )?;
+                if let Some(lang) = &start.lang {
+                    write!(w, " lang=\"{}\"", html::Encoder(lang))?;
+                }
+                if let Some(dir) = &start.dir {
+                    write!(w, " dir=\"{}\"", html::Encoder(dir))?;
+                }
+                if let Some(columns) = start.columns {
+                    write!(w, " style=\"column-count:{}\"", columns)?;
+                }
+                writeln!(w, ">")
+            }
+            Group::End { .. } => writeln!(w, "</div>\n"),
+        }
+    }
+
+    fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        let Group::Start(start) = self else {
+            return Ok(Some(param));
+        };
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("lang") => {
+                start.lang = Some(param.1);
+                None
+            }
+            Some("dir") => {
+                match param.1.as_ref() {
+                    "ltr" | "rtl" | "auto" => start.dir = Some(param.1),
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                }
+                None
+            }
+            _ => Some(param),
+        })
+    }
+
+    fn as_group(&self) -> Option<&Group> {
+        Some(self)
+    }
+}
@@ -2,28 +2,38 @@ use std::fmt::Debug;
 use std::io::{Result as IoResult, Write};
 use std::ops::Deref;
 
+use anyhow::Result as EResult;
+
+use itertools::Itertools;
+
 use crate::blocks::{BlockCommon, BlockType, Parameter};
 use crate::document::Document;
-use crate::errors::Result as EResult;
 use crate::html;
 use crate::text::{Inline, Referenceable, Text, EMPTY_TEXT};
 
 type OResult<T> = EResult<Option<T>>;
 
 /// Writes a section number recursively.
-fn write_section_number(w: &mut dyn Write, number: &[usize]) -> IoResult<()> {
+fn write_section_number(
+    w: &mut dyn Write,
+    number: &[usize],
+    class: &str,
+    document: &Document,
+) -> IoResult<()> {
     if let Some((last, rest)) = number.split_last() {
-        write!(w, "<span class=\"secnum\">")?;
-        write_section_number(w, rest)?;
+        write!(w, "<span")?;
+        document.write_class(w, class)?;
+        write!(w, ">")?;
+        write_section_number(w, rest, class, document)?;
         write!(w, "{}.</span>", last)?;
     }
     Ok(())
 }
 
 /// Returns a section number as a `Text`.
-fn section_number_text(number: &[usize]) -> Text {
+fn section_number_text(number: &[usize], class: &str) -> Text {
     if let Some((last, rest)) = number.split_last() {
-        let mut text = section_number_text(rest).with_class("secnum");
+        let mut text = section_number_text(rest, class).with_class(class);
         text.push(Inline::from(format!("{}.", last)));
         text
     } else {
@@ -40,6 +50,10 @@ pub trait HeadingLike: Debug {
     fn number(&self) -> &[usize];
     fn push_number(&mut self, value: usize);
     fn title(&self) -> &Text;
+    /// Whether this is a filler heading inserted to fill in a skipped level.
+    fn is_filler(&self) -> bool;
+    /// Clears the number and children of the heading, so that it can be added to a document again.
+    fn reset(&mut self);
 
     #[cfg(test)]
     fn eq(&self, other: &dyn HeadingLike) -> bool {
@@ -86,19 +100,44 @@ impl Heading {
 
 impl BlockType for Heading {
     fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
+        if let Some(max_heading) = document
+            .max_heading()
+            .filter(|max_heading| self.level > max_heading.level)
+        {
+            // headings deeper than the maximum are displayed as bold paragraphs
+            write!(w, "<p ")?;
+            write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+            document.write_target_attributes(w)?;
+            document.write_class(
+                w,
+                &format!(
+                    "{} {} h{}",
+                    common.classes(),
+                    document.default_class("collapsed-heading"),
+                    self.level
+                ),
+            )?;
+            write!(w, "><b>")?;
+            if self.numbered && max_heading.numbered {
+                write_section_number(w, &self.number, document.default_class("secnum"), document)?;
+            }
+            self.title.write_inline(w, document)?;
+            return writeln!(w, "</b></p>\n");
+        }
         // start tag
         write!(w, "<{} ", self.tag())?;
-        write!(w, "id=\"{}\" ", html::Encoder(&common.id))?;
-        write!(w, "class=\"{} ", html::Encoder(&common.class))?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_target_attributes(w)?;
         if self.level > 6 {
             // we're just using a `p` tag, so the heading level must be specified as a class
-            write!(w, " h{}\">", self.level)?;
+            document.write_class(w, &format!("{}  h{}", common.classes(), self.level))?;
         } else {
             // we're using a proper heading tag, so no need to specify the heading level as a class
-            write!(w, "\">")?;
+            document.write_class(w, &format!("{} ", common.classes()))?;
         }
+        write!(w, ">")?;
         if self.numbered {
-            write_section_number(w, &self.number)?;
+            write_section_number(w, &self.number, document.default_class("secnum"), document)?;
         }
         self.title.write_inline(w, document)?;
         writeln!(w, "</{}>\n", self.tag())
@@ -121,6 +160,14 @@ impl BlockType for Heading {
         })
     }
 
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.title);
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.title);
+    }
+
     fn as_referenceable(&self) -> Option<&dyn Referenceable> {
         Some(self)
     }
@@ -135,15 +182,26 @@ impl BlockType for Heading {
 }
 
 impl Referenceable for Heading {
-    fn reference_text(&self) -> Text {
-        let mut text = Text::from("section ");
+    fn reference_text(&self, document: &Document) -> Text {
+        let mut text = document.reference_prefix("section", "section ");
         if self.numbered {
-            text.extend(&section_number_text(&self.number))
+            text.extend(&section_number_text(
+                &self.number,
+                document.default_class("secnum"),
+            ))
         } else {
             text.extend(&self.title)
         };
         text
     }
+
+    fn reference_number(&self, _document: &Document) -> Option<String> {
+        if self.numbered {
+            Some(self.number.iter().join("."))
+        } else {
+            None
+        }
+    }
 }
 
 impl HeadingLike for Heading {
@@ -178,6 +236,15 @@ impl HeadingLike for Heading {
     fn title(&self) -> &Text {
         &self.title
     }
+
+    fn is_filler(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.number.clear();
+        self.children = SectionList::new(self.level + 1);
+    }
 }
 
 impl Default for Heading {
@@ -253,6 +320,14 @@ impl HeadingLike for FillerHeading {
     fn title(&self) -> &Text {
         EMPTY_TEXT
     }
+
+    fn is_filler(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.children = SectionList::new(self.children.level);
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
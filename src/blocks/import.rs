@@ -0,0 +1,23 @@
+use std::io::{Result as IoResult, Write};
+
+use crate::blocks::{BlockCommon, BlockType};
+use crate::document::Document;
+use crate::html;
+
+/// A placeholder for an imported file which doesn't exist, when missing imports are allowed.
+#[derive(Debug, Eq, PartialEq)]
+pub struct MissingImport {
+    pub filename: String,
+}
+
+impl BlockType for MissingImport {
+    fn write(&self, w: &mut dyn Write, _common: &BlockCommon, document: &Document) -> IoResult<()> {
+        write!(w, "<div")?;
+        document.write_class(w, document.default_class("missing-import"))?;
+        writeln!(
+            w,
+            ">Missing import: <code>{}</code></div>\n",
+            html::Encoder(&self.filename)
+        )
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::{Result as IoResult, Write};
+
+use crate::blocks::{BlockCommon, BlockType};
+use crate::document::Document;
+use crate::text::Text;
+
+/// A marker for the start or end of a region of the document in a different language, such as
+/// the blocks of an imported file which sets its own `lang`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum LangScope {
+    Start(Text),
+    End,
+}
+
+impl BlockType for LangScope {
+    fn write(&self, w: &mut dyn Write, _common: &BlockCommon, document: &Document) -> IoResult<()> {
+        match self {
+            LangScope::Start(lang) => {
+                write!(w, "<div lang=\"")?;
+                lang.write_inline_plain(w, document)?;
+                writeln!(w, "\">")
+            }
+            LangScope::End => writeln!(w, "</div>\n"),
+        }
+    }
+
+    fn as_lang_scope(&self) -> Option<&LangScope> {
+        Some(self)
+    }
+}
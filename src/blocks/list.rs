@@ -1,10 +1,14 @@
 use std::io::{Result as IoResult, Write};
 
-use crate::blocks::{BlockCommon, BlockType, Parameter};
+use anyhow::Result as EResult;
+
+use itertools::Itertools;
+
+use crate::blocks::{BlockCommon, BlockType, Parameter, UpdateParam};
 use crate::document::Document;
-use crate::errors::Result as EResult;
+use crate::errors::ErrorKind;
 use crate::html;
-use crate::text::Text;
+use crate::text::{Referenceable, Text};
 
 type OResult<T> = EResult<Option<T>>;
 
@@ -12,6 +16,13 @@ type OResult<T> = EResult<Option<T>>;
 pub struct List {
     pub items: Vec<ListItem>,
     pub ordered: bool,
+    /// The marker displayed before each item, instead of the default for the kind of list.
+    pub marker: Option<ListMarker>,
+    /// Whether the markers of the list and its sub-lists are hidden, as for a navigation list.
+    pub flat: bool,
+    /// The number of characters of indentation for each level of nesting, or `None` if any
+    /// deeper indentation starts a sub-list.
+    pub indent: Option<usize>,
 }
 
 impl List {
@@ -43,35 +54,114 @@ impl List {
 impl BlockType for List {
     fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
         write!(w, "<{} ", List::tag(self.ordered))?;
-        write!(w, "id=\"{}\" ", html::Encoder(&common.id))?;
-        write!(w, "class=\"{}\">", html::Encoder(&common.class))?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        if self.flat {
+            let classes = common.classes();
+            let flat = document.default_class("flat");
+            if classes.is_empty() {
+                document.write_class(w, flat)?;
+            } else {
+                document.write_class(w, &format!("{} {}", classes, flat))?;
+            }
+        } else {
+            document.write_class(w, &common.classes())?;
+        }
+        if let Some(marker) = &self.marker {
+            write!(w, " style=\"list-style-type: ")?;
+            marker.write(w)?;
+            write!(w, "\"")?;
+        }
+        write!(w, ">")?;
         List::write_list(w, &self.items, self.ordered, document)?;
         writeln!(w, "</{}>", List::tag(self.ordered))
     }
 
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
-        Ok(match param.0.as_ref() {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("marker") => {
+                self.marker = Some(match param.1.as_ref() {
+                    "disc" => ListMarker::Disc,
+                    "circle" => ListMarker::Circle,
+                    "square" => ListMarker::Square,
+                    "none" => ListMarker::None,
+                    // the marker is written as a CSS string, so it can't contain quotes or
+                    // backslashes.
+                    s if !s.is_empty() && !s.contains(['"', '\\']) => ListMarker::Custom(param.1),
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                });
+                None
+            }
+            Some("indent") => {
+                match param.1.parse::<usize>() {
+                    Ok(indent) if indent > 0 => self.indent = Some(indent),
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                }
+                None
+            }
             Some(_) => Some(param),
             None => match param.1.as_ref() {
                 "ordered" => {
                     self.ordered = true;
                     None
                 }
+                "flat" | "plain" => {
+                    self.flat = true;
+                    None
+                }
                 _ => Some(param),
             },
         })
     }
 
-    #[cfg(test)]
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        ListItem::visit_list(&self.items, f);
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        ListItem::visit_list_mut(&mut self.items, f);
+    }
+
     fn as_list(&self) -> Option<&List> {
         Some(self)
     }
+
+    fn as_mut_list(&mut self) -> Option<&mut List> {
+        Some(self)
+    }
+}
+
+/// The marker displayed before each item of a list.
+#[derive(Debug, Eq, PartialEq)]
+pub enum ListMarker {
+    Disc,
+    Circle,
+    Square,
+    None,
+    /// A custom string, such as a dash.
+    Custom(String),
+}
+
+impl ListMarker {
+    /// Writes the value of the `list-style-type` CSS property for the marker.
+    fn write(&self, w: &mut dyn Write) -> IoResult<()> {
+        match self {
+            ListMarker::Disc => write!(w, "disc"),
+            ListMarker::Circle => write!(w, "circle"),
+            ListMarker::Square => write!(w, "square"),
+            ListMarker::None => write!(w, "none"),
+            ListMarker::Custom(marker) => write!(w, "&quot;{} &quot;", html::Encoder(marker)),
+        }
+    }
 }
 
 #[derive(Debug, Default, Eq, PartialEq)]
 pub struct ListItem {
     pub text: Text,
     pub sublist: Vec<ListItem>,
+    /// The ID of the item, if it can be referenced.
+    pub id: String,
+    /// The number of the item and of the items containing it, if the list is ordered.
+    pub number: Vec<usize>,
 }
 
 impl ListItem {
@@ -79,8 +169,59 @@ impl ListItem {
         Default::default()
     }
 
+    fn visit_list(items: &[ListItem], f: &mut dyn FnMut(&Text)) {
+        for item in items {
+            f(&item.text);
+            ListItem::visit_list(&item.sublist, f);
+        }
+    }
+
+    fn visit_list_mut(items: &mut [ListItem], f: &mut dyn FnMut(&mut Text)) {
+        for item in items {
+            f(&mut item.text);
+            ListItem::visit_list_mut(&mut item.sublist, f);
+        }
+    }
+
+    /// Numbers the given items and their sub-items, if the list is ordered, and calls `f` on each
+    /// item with an ID.
+    pub fn number_list(
+        items: &mut [ListItem],
+        ordered: bool,
+        prefix: &[usize],
+        f: &mut dyn FnMut(&mut ListItem, &[usize]) -> EResult<()>,
+    ) -> EResult<()> {
+        for (i, item) in items.iter_mut().enumerate() {
+            let mut number = prefix.to_vec();
+            number.push(i);
+            if ordered {
+                item.number = number.iter().map(|n| n + 1).collect();
+            }
+            if !item.id.is_empty() {
+                f(item, &number)?;
+            }
+            ListItem::number_list(&mut item.sublist, ordered, &number, f)?;
+        }
+        Ok(())
+    }
+
+    /// Gets the item at the given path of indices into the list and its sub-lists.
+    pub fn get<'a>(items: &'a [ListItem], path: &[usize]) -> &'a ListItem {
+        let (first, rest) = path.split_first().expect("path should not be empty");
+        let item = &items[*first];
+        if rest.is_empty() {
+            item
+        } else {
+            ListItem::get(&item.sublist, rest)
+        }
+    }
+
     fn write(&self, w: &mut dyn Write, ordered: bool, document: &Document) -> IoResult<()> {
-        write!(w, "<li>")?;
+        if self.id.is_empty() {
+            write!(w, "<li>")?;
+        } else {
+            write!(w, "<li id=\"{}\">", html::Encoder(&self.id))?;
+        }
         self.text.write_inline(w, document)?;
         if !self.sublist.is_empty() {
             writeln!(w, "<{}>", List::tag(ordered))?;
@@ -90,3 +231,37 @@ impl ListItem {
         writeln!(w, "</li>")
     }
 }
+
+impl UpdateParam for ListItem {
+    fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("id") => {
+                self.id = param.1;
+                None
+            }
+            Some(_) => Some(param),
+            // there is nothing else to treat a parameter without a name as
+            None => return Err(ErrorKind::Parameter(param.1).into()),
+        })
+    }
+}
+
+impl Referenceable for ListItem {
+    fn reference_text(&self, document: &Document) -> Text {
+        let mut text = document.reference_prefix("item", "item ");
+        if self.number.is_empty() {
+            text.extend(&self.text);
+        } else {
+            text.push(self.number.iter().join("."));
+        }
+        text
+    }
+
+    fn reference_number(&self, _document: &Document) -> Option<String> {
+        if self.number.is_empty() {
+            None
+        } else {
+            Some(self.number.iter().join("."))
+        }
+    }
+}
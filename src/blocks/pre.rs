@@ -0,0 +1,32 @@
+use std::io::{Result as IoResult, Write};
+
+use crate::blocks::{BlockCommon, BlockType};
+use crate::document::Document;
+use crate::html;
+use crate::text::Text;
+
+/// A paragraph whose whitespace is displayed as written.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Preformatted(pub Text);
+
+impl BlockType for Preformatted {
+    fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
+        write!(w, "<p ")?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_class(
+            w,
+            &format!("{} {}", document.default_class("pre"), common.classes()),
+        )?;
+        write!(w, " style=\"white-space: pre-wrap\">")?;
+        self.0.write_inline(w, document)?;
+        writeln!(w, "</p>\n")
+    }
+
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.0);
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.0);
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{Result as IoResult, Write};
+
+use anyhow::Result as EResult;
+
+use crate::blocks::{BlockCommon, BlockType, Parameter};
+use crate::document::Document;
+use crate::html;
+use crate::text::{Referenceable, Text};
+
+type OResult<T> = EResult<Option<T>>;
+
+/// A quoted passage, displayed as a `<blockquote>`.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Quote {
+    /// The paragraphs of the quotation.
+    pub paragraphs: Vec<Text>,
+    /// The person or work the quotation is from, displayed after it.
+    pub attribution: Option<Text>,
+    /// The URL of the source of the quotation, given by the `cite` parameter.
+    pub cite: Option<String>,
+}
+
+impl Quote {
+    pub fn new() -> Quote {
+        Default::default()
+    }
+}
+
+impl BlockType for Quote {
+    fn write(&self, w: &mut dyn Write, common: &BlockCommon, document: &Document) -> IoResult<()> {
+        write!(w, "<blockquote ")?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_class(
+            w,
+            &format!("{} {}", document.default_class("quote"), common.classes()),
+        )?;
+        if let Some(cite) = &self.cite {
+            write!(
+                w,
+                " cite=\"{}\"",
+                html::Encoder(&document.resolve_url(cite))
+            )?;
+        }
+        writeln!(w, ">")?;
+        for paragraph in &self.paragraphs {
+            write!(w, "<p>")?;
+            paragraph.write_inline(w, document)?;
+            writeln!(w, "</p>")?;
+        }
+        if let Some(attribution) = &self.attribution {
+            write!(w, "<footer>")?;
+            attribution.write_inline(w, document)?;
+            writeln!(w, "</footer>")?;
+        }
+        writeln!(w, "</blockquote>\n")
+    }
+
+    fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("cite") => {
+                self.cite = Some(param.1);
+                None
+            }
+            _ => Some(param),
+        })
+    }
+
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        for paragraph in &self.paragraphs {
+            f(paragraph);
+        }
+        if let Some(attribution) = &self.attribution {
+            f(attribution);
+        }
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        for paragraph in &mut self.paragraphs {
+            f(paragraph);
+        }
+        if let Some(attribution) = &mut self.attribution {
+            f(attribution);
+        }
+    }
+
+    fn as_referenceable(&self) -> Option<&dyn Referenceable> {
+        Some(self)
+    }
+}
+
+impl Referenceable for Quote {
+    fn reference_text(&self, document: &Document) -> Text {
+        let mut text = document.reference_prefix("quote", "quotation");
+        if let Some(attribution) = &self.attribution {
+            if !attribution.starts_with(' ') {
+                text.push(String::from(" "));
+            }
+            text.extend(attribution);
+        }
+        text
+    }
+}
+
+/// The kind of a line of a quotation.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub enum QuoteLine {
+    /// A paragraph of the quotation.
+    #[default]
+    Paragraph,
+    /// The attribution of the quotation.
+    Attribution,
+}
+
+impl QuoteLine {
+    /// Updates with the given parameter. If the parameter was not updated, returns the parameter.
+    pub fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
+        Ok(match param.0.as_ref() {
+            Some(_) => Some(param),
+            None => match param.1.as_ref() {
+                "attribution" => {
+                    *self = QuoteLine::Attribution;
+                    None
+                }
+                _ => Some(param),
+            },
+        })
+    }
+}
@@ -0,0 +1,26 @@
+use std::io::{Result as IoResult, Write};
+
+use crate::blocks::{BlockCommon, BlockType};
+use crate::document::Document;
+
+/// A block kept as the lines it was written as, without parsing its text, which is written to the
+/// output unchanged. This is used for blocks which would otherwise be paragraphs when the default
+/// block type is `raw`, for embedding data to be processed by a post-processor.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Raw {
+    pub lines: Vec<String>,
+}
+
+impl BlockType for Raw {
+    fn write(
+        &self,
+        w: &mut dyn Write,
+        _common: &BlockCommon,
+        _document: &Document,
+    ) -> IoResult<()> {
+        for line in &self.lines {
+            writeln!(w, "{}", line)?;
+        }
+        writeln!(w)
+    }
+}
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 use std::io::{Result as IoResult, Write};
 
+use anyhow::Result as EResult;
+
 use crate::blocks::{BlockCommon, BlockType, Parameter};
 use crate::document::Document;
-use crate::errors::{ErrorKind, Result as EResult};
+use crate::errors::ErrorKind;
 use crate::text::Text;
 
 type OResult<T> = EResult<Option<T>>;
@@ -0,0 +1,24 @@
+use std::io::{Result as IoResult, Write};
+
+use crate::blocks::{BlockCommon, BlockType};
+use crate::document::Document;
+use crate::text::Text;
+
+/// A run of text written without a surrounding element.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct TextRun(pub Text);
+
+impl BlockType for TextRun {
+    fn write(&self, w: &mut dyn Write, _common: &BlockCommon, document: &Document) -> IoResult<()> {
+        self.0.write_inline(w, document)?;
+        writeln!(w)
+    }
+
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.0);
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.0);
+    }
+}
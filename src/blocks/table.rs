@@ -1,10 +1,10 @@
 use std::io::{Result as IoResult, Write};
 
-use anyhow::Context;
+use anyhow::Result as EResult;
 
 use crate::blocks::{BlockCommon, BlockType, Parameter, UpdateParam};
 use crate::document::Document;
-use crate::errors::{ErrorKind, Result as EResult};
+use crate::errors::{Context, ErrorKind};
 use crate::html;
 use crate::text::{Referenceable, Text};
 
@@ -15,39 +15,48 @@ pub struct Table {
     pub title: Text,
     pub numbered: bool,
     pub number: usize,
+    /// A number specified by the `number` parameter, to use instead of the automatic number.
+    pub explicit_number: Option<usize>,
     pub rows: Vec<Row>,
     pub columns: Vec<Column>,
+    /// The character which separates cells.
+    pub separator: char,
+    /// Whether the title is displayed in a header row spanning the whole table, rather than in a
+    /// caption.
+    pub banner: bool,
+    /// Whether the table scrolls horizontally when it is too wide, rather than overflowing.
+    pub scroll: bool,
+    /// Whether the caption is displayed below the table, rather than above it.
+    pub caption_bottom: bool,
+    /// Whether the table is a plain data or layout table, without a caption or number.
+    pub plain: bool,
 }
 
 impl Table {
     pub fn new() -> Table {
         Default::default()
     }
-}
 
-impl BlockType for Table {
-    fn write(
-        &self,
-        mut w: &mut dyn Write,
-        common: &BlockCommon,
-        document: &Document,
-    ) -> IoResult<()> {
-        write!(w, "<table ")?;
-        write!(w, "id=\"{}\" ", html::Encoder(&common.id))?;
-        write!(w, "class=\"{}\">", html::Encoder(&common.class))?;
-        write!(w, "<caption>")?;
-        write!(w, r#"<span class="table-heading-prefix">Table"#)?;
+    fn write_title(&self, w: &mut dyn Write, document: &Document) -> IoResult<()> {
+        write!(w, "<span")?;
+        document.write_class(w, document.default_class("table-heading-prefix"))?;
+        write!(w, ">Table")?;
         if self.numbered {
-            write!(w, " {}", self.number)?;
+            write!(w, " {}", document.format_number("table", self.number))?;
         }
         write!(w, ":</span> ")?;
-        self.title.write_inline(w, document)?;
-        writeln!(w, "</caption>")?;
+        self.title.write_inline(w, document)
+    }
+
+    /// Returns the index of the column each cell starts in, for each row, taking into account
+    /// cells from earlier rows which span multiple rows.
+    fn cell_columns(&self) -> Vec<Vec<usize>> {
         // for recording when a cell is a continuation from an earlier row, to correctly count
         // columns
         let mut continuation_cells = Vec::<usize>::with_capacity(self.columns.len());
+        let mut columns = Vec::with_capacity(self.rows.len());
         for row in &self.rows {
-            write!(w, "<tr class=\"{}\">", html::Encoder(&row.class))?;
+            let mut row_columns = Vec::with_capacity(row.cells.len());
             let mut col = 0;
             for cell in &row.cells {
                 // increment col until we get to a free column
@@ -68,27 +77,150 @@ impl BlockType for Table {
                 for n in &mut continuation_cells[col..col + cell.cols] {
                     *n = cell.rows.max(*n).saturating_sub(1);
                 }
-                cell.write(&mut w, row, self.columns.get(col), document)?;
+                row_columns.push(col);
                 col += cell.cols;
             }
+            columns.push(row_columns);
+        }
+        columns
+    }
+}
+
+impl BlockType for Table {
+    fn write(
+        &self,
+        mut w: &mut dyn Write,
+        common: &BlockCommon,
+        document: &Document,
+    ) -> IoResult<()> {
+        if self.scroll {
+            write!(w, "<div")?;
+            document.write_class(w, document.default_class("table-scroll"))?;
+            write!(w, " style=\"overflow-x:auto\">")?;
+        }
+        write!(w, "<table ")?;
+        write!(w, "id=\"{}\"", html::Encoder(&common.id))?;
+        document.write_target_attributes(w)?;
+        let mut class = common.classes().into_owned();
+        if let Some(theme) = document.theme_class() {
+            class.push(' ');
+            class.push_str(&theme);
+        }
+        document.write_class(w, &class)?;
+        write!(w, ">")?;
+        let columns = self.cell_columns();
+        if self.banner && !self.plain {
+            let width = self
+                .rows
+                .iter()
+                .zip(&columns)
+                .flat_map(|(row, columns)| {
+                    row.cells
+                        .iter()
+                        .zip(columns)
+                        .map(|(cell, col)| col + cell.cols)
+                })
+                .max()
+                .unwrap_or(0)
+                .max(self.columns.len());
+            write!(w, "<tr")?;
+            document.write_class(w, document.default_class("banner"))?;
+            write!(w, ">")?;
+            write!(w, "<th colspan=\"{}\" scope=\"colgroup\">", width.max(1))?;
+            self.write_title(w, document)?;
+            writeln!(w, "</th></tr>")?;
+        } else if !self.plain {
+            if self.caption_bottom {
+                write!(w, "<caption")?;
+                document.write_class(w, document.default_class("caption-bottom"))?;
+                write!(w, " style=\"caption-side: bottom\">")?;
+            } else {
+                write!(w, "<caption>")?;
+            }
+            self.write_title(w, document)?;
+            writeln!(w, "</caption>")?;
+        }
+        for (row, columns) in self.rows.iter().zip(&columns) {
+            write!(w, "<tr")?;
+            document.write_class(w, &row.class)?;
+            write!(w, ">")?;
+            for (cell, &col) in row.cells.iter().zip(columns) {
+                cell.write(&mut w, row, self.columns.get(col), document)?;
+            }
             writeln!(w, "</tr>")?;
         }
-        writeln!(w, "</table>\n")
+        if self.scroll {
+            writeln!(w, "</table></div>\n")
+        } else {
+            writeln!(w, "</table>\n")
+        }
     }
 
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
-        Ok(match param.0.as_ref() {
+        Ok(match param.0.as_ref().map(|n| n.as_ref()) {
+            Some("number") => {
+                let number = param.1.parse::<usize>().context(ErrorKind::Parse)?;
+                if number == 0 {
+                    // numbering starts from 1
+                    return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into());
+                }
+                self.explicit_number = Some(number);
+                None
+            }
+            Some("sep") => {
+                let mut chars = param.1.chars();
+                self.separator = match (chars.next(), chars.next()) {
+                    (Some(c), None) if !c.is_whitespace() => c,
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                };
+                None
+            }
+            Some("caption") => {
+                self.caption_bottom = match param.1.as_ref() {
+                    "top" => false,
+                    "bottom" => true,
+                    _ => return Err(ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()),
+                };
+                None
+            }
             Some(_) => Some(param),
             None => match param.1.as_ref() {
                 "nonumber" => {
                     self.numbered = false;
                     None
                 }
+                "scroll" => {
+                    self.scroll = true;
+                    None
+                }
+                "banner" => {
+                    self.banner = true;
+                    None
+                }
+                "plain" => {
+                    self.plain = true;
+                    self.numbered = false;
+                    None
+                }
                 _ => Some(param),
             },
         })
     }
 
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(&self.title);
+        for cell in self.rows.iter().flat_map(|row| &row.cells) {
+            f(&cell.text);
+        }
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(&mut self.title);
+        for cell in self.rows.iter_mut().flat_map(|row| &mut row.cells) {
+            f(&mut cell.text);
+        }
+    }
+
     fn as_mut_table(&mut self) -> Option<&mut Table> {
         Some(self)
     }
@@ -99,15 +231,23 @@ impl BlockType for Table {
 }
 
 impl Referenceable for Table {
-    fn reference_text(&self) -> Text {
-        let mut text = Text::from("table ");
+    fn reference_text(&self, document: &Document) -> Text {
+        let mut text = document.reference_prefix("table", "table ");
         if self.numbered {
-            text.push(format!("{}", self.number));
+            text.push(document.format_number("table", self.number));
         } else {
             text.extend(&self.title);
         }
         text
     }
+
+    fn reference_number(&self, document: &Document) -> Option<String> {
+        if self.numbered {
+            Some(document.format_number("table", self.number))
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for Table {
@@ -116,8 +256,14 @@ impl Default for Table {
             title: Default::default(),
             numbered: true,
             number: 0,
+            explicit_number: None,
             rows: Default::default(),
             columns: Default::default(),
+            separator: '|',
+            banner: false,
+            scroll: false,
+            caption_bottom: false,
+            plain: false,
         }
     }
 }
@@ -158,6 +304,8 @@ impl UpdateParam for Row {
 pub struct Column {
     pub header: bool,
     pub class: String,
+    /// Whether the text of cells in the column may wrap, or `None` to leave it unspecified.
+    pub wrap: Option<bool>,
 }
 
 impl Column {
@@ -176,6 +324,8 @@ impl UpdateParam for Column {
             None => {
                 match param.1.as_ref() {
                     "header" => self.header = true,
+                    "wrap" => self.wrap = Some(true),
+                    "nowrap" => self.wrap = Some(false),
                     _ => self.class = param.1,
                 }
                 None
@@ -190,6 +340,8 @@ pub struct Cell {
     pub rows: usize,
     pub cols: usize,
     pub class: String,
+    /// Whether the text of the cell may wrap, or `None` to use the setting of the column.
+    pub wrap: Option<bool>,
     pub text: Text,
 }
 
@@ -208,33 +360,40 @@ impl Cell {
         let header_row = row.header;
         let header_col = col.map(|col| col.header).unwrap_or(false);
         if header_row {
-            write!(w, "<th ")?;
+            write!(w, "<th")?;
             if self.cols > 1 {
-                write!(w, "scope=\"colgroup\" ")?;
+                write!(w, " scope=\"colgroup\"")?;
             } else {
-                write!(w, "scope=\"col\" ")?;
+                write!(w, " scope=\"col\"")?;
             }
         } else if header_col {
-            write!(w, "<th ")?;
+            write!(w, "<th")?;
             if self.rows > 1 {
-                write!(w, "scope=\"rowgroup\" ")?;
+                write!(w, " scope=\"rowgroup\"")?;
             } else {
-                write!(w, "scope=\"row\" ")?;
+                write!(w, " scope=\"row\"")?;
             }
         } else {
-            write!(w, "<td ")?;
+            write!(w, "<td")?;
         }
         if self.cols > 1 {
-            write!(w, "colspan=\"{}\" ", self.cols)?;
+            write!(w, " colspan=\"{}\"", self.cols)?;
         }
         if self.rows > 1 {
-            write!(w, "rowspan=\"{}\" ", self.rows)?;
+            write!(w, " rowspan=\"{}\"", self.rows)?;
         }
-        write!(w, "class=\"{}", html::Encoder(&self.class))?;
+        let mut class = self.class.clone();
         if let Some(col) = col {
-            write!(w, " {}", html::Encoder(&col.class))?;
+            class.push(' ');
+            class.push_str(&col.class);
         }
-        write!(w, r#"">"#)?;
+        match self.wrap.or_else(|| col.and_then(|col| col.wrap)) {
+            Some(true) => class.push_str(" wrap"),
+            Some(false) => class.push_str(" nowrap"),
+            None => {}
+        }
+        document.write_class(w, &class)?;
+        write!(w, ">")?;
         self.text.write_inline(w, document)?;
         if header_row || header_col {
             write!(w, "</th>")?;
@@ -248,10 +407,18 @@ impl Cell {
 impl UpdateParam for Cell {
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
         Ok(match param.0.as_ref().map(|n| n.as_ref()) {
-            Some("class") | None => {
+            Some("class") => {
                 self.class = param.1;
                 None
             }
+            None => {
+                match param.1.as_ref() {
+                    "wrap" => self.wrap = Some(true),
+                    "nowrap" => self.wrap = Some(false),
+                    _ => self.class = param.1,
+                }
+                None
+            }
             Some("rows") => {
                 self.rows = param.1.parse::<usize>().context(ErrorKind::Parse)?;
                 None
@@ -271,6 +438,7 @@ impl Default for Cell {
             rows: 1,
             cols: 1,
             class: Default::default(),
+            wrap: None,
             text: Default::default(),
         }
     }
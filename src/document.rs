@@ -1,23 +1,36 @@
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::fmt::Debug;
-use std::fs::File;
-use std::io::{BufReader, Result as IoResult, Write};
-use std::path::Path;
+use std::fmt::{self, Debug};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Result as IoResult, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use anyhow::Context;
+use anyhow::Result as EResult;
 use itertools::Itertools;
 
 use crate::blocks::{
-    control::DocumentControl,
+    bibliography::Entry as BibliographyEntry,
+    contents::Contents,
+    control::{
+        AutoToc, DefaultBlock, DocumentControl, Formatting, MaxHeading, NumberStyle, Undefined,
+    },
+    group::Group,
     heading::{FillerHeading, HeadingLike, SectionList},
+    import::MissingImport,
+    lang::LangScope,
+    list::ListItem,
     replacements::Replacements,
-    Block, BlockCommon,
+    Block, BlockCommon, BlockType,
 };
-use crate::errors::{ErrorKind, Result as EResult};
-use crate::input::Input;
-use crate::text::Text;
+use crate::errors::{Context, Error, ErrorKind, Result};
+use crate::html::{self, CharRefWriter};
+use crate::input::{BidiControls, Input};
+use crate::text::{Inline, InlineType, Metadata, Referenceable, Text};
 
 #[derive(Debug, Default)]
 pub struct Document {
@@ -28,20 +41,56 @@ pub struct Document {
     sections: SectionList,
     /// A map from IDs to indices into the `blocks` field.
     ids: HashMap<String, usize>,
+    /// A set of indices into the `blocks` field corresponding to blocks whose IDs were generated
+    /// automatically.
+    generated_ids: HashSet<usize>,
+    /// A map from IDs of bibliography entries to indices into the `blocks` field and indices of
+    /// the entries within the bibliography.
+    citations: HashMap<String, (usize, usize)>,
+    /// A map from IDs of list items to indices into the `blocks` field and the indices of the
+    /// items within the list and its sub-lists.
+    list_items: HashMap<String, (usize, Vec<usize>)>,
     /// A map of defined replacements.
     replacements: Replacements,
     /// A list of indices into the `blocks` field corresponding to the tables.
     tables: Vec<usize>,
     /// A list of indices into the `blocks` field corresponding to the glosses.
     glosses: Vec<usize>,
+    /// The footnotes, in order.
+    footnotes: Vec<FootnoteEntry>,
+    /// The IDs of elements which aren't blocks, such as footnotes, so that blocks can't use them.
+    reserved_ids: HashSet<String>,
+    /// The ID of the title displayed at the top of the document, if a table of contents links to
+    /// it.
+    title_id: Option<String>,
+    /// The ID of the table of contents generated by `:autotoc: after-title`.
+    auto_toc_id: Option<String>,
     /// The last table number.
     table_number: usize,
+    /// The numbers which have been used for tables.
+    table_numbers: HashSet<usize>,
     /// The last gloss number.
     gloss_number: usize,
+    /// The numbers which have been used for glosses.
+    gloss_numbers: HashSet<usize>,
+    /// Whether tables and glosses share the table counter, rather than being numbered
+    /// separately.
+    shared_numbers: bool,
+    /// Whether each group which has been started but not ended is a multi-column layout, from the
+    /// outermost inwards.
+    open_groups: Vec<bool>,
+    /// The variables which have been set, for `:if:` blocks.
+    variables: HashSet<String>,
+    /// For each `:if:` block which hasn't been ended, whether the blocks after it are included,
+    /// and the line it starts on.
+    conditions: Vec<(bool, usize)>,
     /// The first unused number for blocks without an ID.
     noid_index: usize,
     /// The title of the document.
     title: Option<Text>,
+    /// Whether to use the title of the first section as the title of the document, if it has no
+    /// title.
+    auto_title: bool,
     /// The author of the document.
     author: Option<Text>,
     /// The description of the document.
@@ -50,12 +99,369 @@ pub struct Document {
     stylesheets: Vec<Text>,
     /// The global `lang` attribute for the document.
     lang: Option<Text>,
+    /// A map from kinds of block to the text preceding their numbers in references.
+    reference_prefixes: HashMap<String, Text>,
+    /// A map from kinds of block to the styles of their numbers.
+    number_styles: HashMap<String, NumberStyle>,
+    /// A map from kinds of block to their accessible labels.
+    aria_labels: HashMap<String, Text>,
+    /// A map from kinds of block to the prefixes of their automatically generated IDs.
+    id_prefixes: HashMap<String, String>,
+    /// A map from gloss abbreviations to their meanings.
+    abbreviations: HashMap<String, Text>,
+    /// How to display undefined references and replacements.
+    undefined: Option<Undefined>,
+    /// How emphasis, strong emphasis, italics, and bold are written.
+    formatting: Option<Formatting>,
+    /// The doctype of the document, or `none` to omit the doctype.
+    doctype: Option<String>,
+    /// The theme of the document, which is added as a class to tables and glosses.
+    theme: Option<String>,
+    /// The separator between variant forms.
+    variant_separator: Option<String>,
+    /// Where a table of contents is generated automatically.
+    auto_toc: Option<AutoToc>,
+    /// How blocks which aren't headings or block directives are parsed, as set by the last
+    /// `:defaultblock:` block.
+    default_block: DefaultBlock,
+    /// The base URL prepended to relative URLs of links and stylesheets.
+    base_url: Option<String>,
+    /// A map from the URLs of local files used by the document to the names of their copies in
+    /// the output directory.
+    assets: HashMap<String, String>,
+    /// The deepest level of heading displayed as a heading.
+    max_heading: Option<MaxHeading>,
+    /// Additional attributes for the `<html>` element.
+    html_attributes: Vec<(String, Text)>,
+    /// The time the document was last updated, in seconds since the Unix epoch, to display in the
+    /// footer.
+    timestamp: Option<u64>,
+    /// The ID of the section marked as the current section in tables of contents.
+    active_section: Option<String>,
+    /// Whether to omit the `<meta name="generator">` tag.
+    omit_generator: bool,
+    /// Whether to omit the built-in classes of elements.
+    omit_default_classes: bool,
+    /// Whether reading files named in the document is disabled.
+    safe: bool,
+    /// Whether an imported file which doesn't exist is replaced by a placeholder, with a warning,
+    /// instead of being an error.
+    lenient_imports: bool,
+    /// Whether blocks which can be referenced can be focused, so that following a link to one
+    /// moves the keyboard focus to it.
+    focusable_targets: bool,
+    /// How to handle bidirectional control characters in the input.
+    bidi_controls: BidiControls,
+    /// Whether to print each parameter list in the input to standard error as it is parsed.
+    debug_params: bool,
+    /// Warnings about the input which has been read.
+    input_warnings: Vec<ErrorKind>,
+    /// How to handle elements whose IDs are already used.
+    duplicate_ids: DuplicateIds,
+    /// The files the document is written to, if it is split into multiple files.
+    files: Vec<OutputFile>,
+    /// The index into the `files` field of the file currently being written.
+    current_file: Cell<usize>,
+    /// The index into the `blocks` field of the block currently being written, if any.
+    current_block: Cell<Option<usize>>,
+    /// The functions the HTML of the document is passed through before it is written.
+    post_processors: PostProcessors,
+    /// Whether a field of the metadata is being written, so that a field which refers to itself,
+    /// such as a title containing `:doctitle:`, isn't expanded forever.
+    writing_metadata: Cell<bool>,
+    /// Whether the contents of a link are being written.
+    writing_link: Cell<bool>,
+    /// Whether the text of a cross reference is being written.
+    writing_reference: Cell<bool>,
+}
+
+/// Functions which transform the HTML of a document before it is written, in the order they are
+/// applied.
+#[derive(Default)]
+struct PostProcessors(Vec<Box<dyn Fn(String) -> String>>);
+
+impl Debug for PostProcessors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PostProcessors({})", self.0.len())
+    }
+}
+
+/// How to handle an element whose ID is already used by another element.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicateIds {
+    /// Stop with an error.
+    #[default]
+    Error,
+    /// Keep the ID, with a warning. References to the ID refer to the first element with it.
+    Warn,
+    /// Append a number to the ID to make it unique, as in `id-2`. References to the ID refer to
+    /// the first element with it.
+    Suffix,
+}
+
+/// Checks whether the ID is already used, as determined by `used`, and handles it as specified by
+/// `policy` if it is. Returns whether the element should be registered under its (possibly
+/// changed) ID, which is `false` if the duplicate ID should be warned about.
+fn claim_id(policy: DuplicateIds, id: &mut String, used: &dyn Fn(&str) -> bool) -> EResult<bool> {
+    if !used(id) {
+        return Ok(true);
+    }
+    match policy {
+        DuplicateIds::Error => Err(ErrorKind::Id(id.clone()).into()),
+        DuplicateIds::Warn => Ok(false),
+        DuplicateIds::Suffix => {
+            *id = (2..)
+                .map(|i| format!("{}-{}", id, i))
+                .find(|suffixed| !used(suffixed))
+                .unwrap();
+            Ok(true)
+        }
+    }
+}
+
+/// The ID of the title displayed at the top of the document, if it is given one.
+const TITLE_ID: &str = "title";
+
+/// The ID of the table of contents generated by `:autotoc:`.
+const AUTO_TOC_ID: &str = "toc";
+
+/// A footnote of the document.
+#[derive(Debug)]
+struct FootnoteEntry {
+    /// The index into the `blocks` field of the block containing the footnote.
+    block: usize,
+    text: Text,
+    /// The ID of the text of the footnote.
+    id: String,
+    /// The ID of the reference to the footnote.
+    ref_id: String,
+}
+
+/// A file which part of a split document is written to.
+#[derive(Debug)]
+pub struct OutputFile {
+    /// The name of the file.
+    pub name: String,
+    /// The index into the `blocks` field of the first block in the file.
+    start: usize,
+}
+
+/// The last numbers used for top-level sections, tables, and glosses, so that the numbering of a
+/// document can continue on from another one.
+///
+/// It is written to and read from a file as lines of the form `table 3`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Numbering {
+    /// The last number used for a top-level section.
+    pub sections: usize,
+    /// The last number used for a table.
+    pub tables: usize,
+    /// The last number used for a gloss.
+    pub glosses: usize,
+}
+
+impl fmt::Display for Numbering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "section {}", self.sections)?;
+        writeln!(f, "table {}", self.tables)?;
+        writeln!(f, "gloss {}", self.glosses)
+    }
+}
+
+impl FromStr for Numbering {
+    type Err = ErrorKind;
+
+    fn from_str(s: &str) -> Result<Numbering, ErrorKind> {
+        let mut numbering = Numbering::default();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let (kind, number) = line
+                .split_once(char::is_whitespace)
+                .and_then(|(kind, number)| Some((kind, number.trim().parse().ok()?)))
+                .ok_or_else(|| ErrorKind::NumberingLine(line.into()))?;
+            match kind {
+                "section" => numbering.sections = number,
+                "table" => numbering.tables = number,
+                "gloss" => numbering.glosses = number,
+                _ => return Err(ErrorKind::NumberingLine(line.into())),
+            }
+        }
+        Ok(numbering)
+    }
+}
+
+/// The progress of reading a document, reported after each block is read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Progress {
+    /// The number of blocks which have been read.
+    pub blocks: usize,
+    /// The number of bytes which have been read.
+    pub bytes: usize,
 }
 
 impl Document {
+    /// Reads a document from the given input.
+    pub fn from_reader(input: impl BufRead) -> Result<Document> {
+        Document::from_reader_with_progress(input, None)
+    }
+
+    /// Reads a document from the given input, calling the `progress` callback, if one is given,
+    /// after each block is read.
+    pub fn from_reader_with_progress(
+        input: impl BufRead,
+        progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> Result<Document> {
+        let mut document = Document::default();
+        document.read(input, progress)?;
+        Ok(document)
+    }
+
+    /// Reads blocks from the given input and adds them to the document, calling the `progress`
+    /// callback, if one is given, after each block is read.
+    pub fn read(
+        &mut self,
+        input: impl BufRead,
+        mut progress: Option<&mut dyn FnMut(Progress)>,
+    ) -> Result<()> {
+        let mut input = Input::new(input);
+        input.set_bidi_controls(self.bidi_controls);
+        input.set_debug_params(self.debug_params);
+        let mut blocks = 0;
+        loop {
+            input.set_default_block(self.default_block);
+            let Some(block) = input.next_block()?.parse()? else {
+                break;
+            };
+            self.add_block(block)?;
+            self.input_warnings.extend(input.take_warnings());
+            if let Some(progress) = &mut progress {
+                blocks += 1;
+                progress(Progress {
+                    blocks,
+                    bytes: input.bytes_read(),
+                });
+            }
+        }
+        self.check_conditions_closed()
+    }
+
+    /// Returns an error if there are any `:if:` blocks which haven't been ended.
+    fn check_conditions_closed(&self) -> Result<()> {
+        match self.conditions.last() {
+            Some(&(_, line)) => Err(ErrorKind::UnclosedIf(line).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets a variable, so that the blocks after an `:if:` block for it which are added afterwards
+    /// are included in the document.
+    pub fn define(&mut self, variable: impl Into<String>) {
+        self.variables.insert(variable.into());
+    }
+
+    /// Sets how to handle bidirectional control characters in input which is read afterwards.
+    pub fn set_bidi_controls(&mut self, bidi_controls: BidiControls) {
+        self.bidi_controls = bidi_controls;
+    }
+
+    /// Sets whether to print each parameter list in input which is read afterwards to standard
+    /// error as it is parsed, with the line the block containing it starts on.
+    pub fn set_debug_params(&mut self, debug_params: bool) {
+        self.debug_params = debug_params;
+    }
+
+    /// Gets the warnings about the input which has been read, such as bidirectional control
+    /// characters which were kept, and duplicate IDs.
+    pub fn input_warnings(&self) -> &[ErrorKind] {
+        &self.input_warnings
+    }
+
+    /// Sets how to handle elements whose IDs are already used by other elements, which are added
+    /// afterwards.
+    pub fn set_duplicate_ids(&mut self, duplicate_ids: DuplicateIds) {
+        self.duplicate_ids = duplicate_ids;
+    }
+
+    /// Gets the last numbers used for top-level sections, tables, and glosses, so that another
+    /// document can continue the numbering with [`set_numbering`](Document::set_numbering).
+    pub fn numbering(&self) -> Numbering {
+        let last = |number: usize, used: &HashSet<usize>| {
+            used.iter().copied().max().unwrap_or(0).max(number)
+        };
+        let tables = last(self.table_number, &self.table_numbers);
+        Numbering {
+            sections: self.sections.last_child_number,
+            tables,
+            glosses: if self.shared_numbers {
+                tables
+            } else {
+                last(self.gloss_number, &self.gloss_numbers)
+            },
+        }
+    }
+
+    /// Continues the numbering of top-level sections, tables, and glosses which are added
+    /// afterwards on from the given numbers, such as those of a previous document. If tables and
+    /// glosses share a counter, it continues on from the number for tables.
+    pub fn set_numbering(&mut self, numbering: Numbering) {
+        self.sections.last_child_number = numbering.sections;
+        self.table_number = numbering.tables;
+        self.gloss_number = numbering.glosses;
+    }
+
+    /// Sets the number of the next top-level section which is added.
+    pub fn set_start_section_number(&mut self, number: usize) {
+        self.sections.last_child_number = number.saturating_sub(1);
+    }
+
+    /// Sets the number of the next table which is added without an explicit number.
+    pub fn set_start_table_number(&mut self, number: usize) {
+        self.table_number = number.saturating_sub(1);
+    }
+
+    /// Sets the number of the next gloss which is added without an explicit number.
+    pub fn set_start_gloss_number(&mut self, number: usize) {
+        self.gloss_number = number.saturating_sub(1);
+    }
+
+    /// Whether the ID is used by a block, bibliography entry, or list item.
+    fn is_id_used(&self, id: &str) -> bool {
+        self.ids.contains_key(id)
+            || self.citations.contains_key(id)
+            || self.list_items.contains_key(id)
+            || self.reserved_ids.contains(id)
+    }
+
     /// Adds the given block to the document.
-    pub fn add_block(&mut self, mut block: Block) -> EResult<()> {
+    pub fn add_block(&mut self, mut block: Block) -> Result<()> {
+        let included = self.conditions.last().is_none_or(|&(included, _)| included);
+        match block.kind.as_control() {
+            Some(DocumentControl::If { variable, negated }) => {
+                let set = self.variables.contains(variable);
+                self.conditions
+                    .push((included && set != *negated, block.common.start_line));
+                return Ok(());
+            }
+            Some(DocumentControl::EndIf) => {
+                self.conditions
+                    .pop()
+                    .ok_or(ErrorKind::EndIf(block.common.start_line))?;
+                return Ok(());
+            }
+            _ if !included => return Ok(()),
+            _ => {}
+        }
+        if let Some(&Group::End { columns }) = block.kind.as_group() {
+            if self.open_groups.last() != Some(&columns) {
+                return Err(if columns {
+                    ErrorKind::EndColumns(block.common.start_line)
+                } else {
+                    ErrorKind::EndGroup(block.common.start_line)
+                }
+                .into());
+            }
+        }
         let mut idx = self.blocks.len();
+        let generated_id = block.common.id.is_empty();
         if let Some(control) = block.kind.as_control() {
             self.control(control)?;
         }
@@ -65,38 +471,219 @@ impl Document {
         if let Some(replacements) = block.kind.as_mut_replacements() {
             self.replacements.update(replacements);
         }
+        // the IDs of the block, its bibliography entries, and its list items are all claimed
+        // before any of them are recorded, so that they aren't left behind if one of them is a
+        // duplicate
+        let mut claimed = HashSet::new();
+        let mut citations = Vec::new();
+        let mut list_items = Vec::new();
+        let mut warnings = Vec::new();
+        if let Some(bibliography) = block.kind.as_mut_bibliography() {
+            for (i, entry) in bibliography.entries.iter_mut().enumerate() {
+                if bibliography.numbered {
+                    entry.number = Some(i + 1);
+                }
+                if entry.id.is_empty() {
+                    // entries without an ID can't be cited
+                    continue;
+                }
+                if claim_id(self.duplicate_ids, &mut entry.id, &|id| {
+                    self.is_id_used(id) || claimed.contains(id)
+                })? {
+                    claimed.insert(entry.id.clone());
+                    citations.push((entry.id.clone(), i));
+                } else {
+                    warnings.push(ErrorKind::Id(entry.id.clone()));
+                }
+            }
+        }
+        if let Some(list) = block.kind.as_mut_list() {
+            ListItem::number_list(&mut list.items, list.ordered, &[], &mut |item, path| {
+                if claim_id(self.duplicate_ids, &mut item.id, &|id| {
+                    self.is_id_used(id) || claimed.contains(id)
+                })? {
+                    claimed.insert(item.id.clone());
+                    list_items.push((item.id.clone(), path.to_vec()));
+                } else {
+                    warnings.push(ErrorKind::Id(item.id.clone()));
+                }
+                Ok(())
+            })?;
+        }
+        // the markers of an imported file's `lang` aren't written with an ID
+        let lang_scope = block.kind.as_lang_scope().is_some();
+        let noid = block.common.id.is_empty() && !lang_scope;
+        if noid {
+            block.common.id = format!("{}{}", self.id_prefix("noid", "__no-id-"), self.noid_index);
+        }
+        let record_id = if lang_scope {
+            false
+        } else if block.kind.as_contents().is_some() && self.auto_toc == Some(AutoToc::None) {
+            // tables of contents aren't written, so they can't be referred to
+            false
+        } else if claim_id(self.duplicate_ids, &mut block.common.id, &|id| {
+            self.is_id_used(id) || claimed.contains(id)
+        })? {
+            true
+        } else {
+            warnings.push(ErrorKind::Id(block.common.id.clone()));
+            false
+        };
         if let Some(table) = block.kind.as_mut_table() {
             if table.numbered {
-                self.table_number += 1;
-                table.number = self.table_number;
+                table.number = assign_number(
+                    &mut self.table_number,
+                    &mut self.table_numbers,
+                    table.explicit_number,
+                )
+                .ok_or(ErrorKind::Number(
+                    "table",
+                    table.explicit_number.unwrap_or(0),
+                ))?;
+            }
+            if !table.plain {
+                self.tables.push(idx);
             }
-            self.tables.push(idx);
         }
         if let Some(gloss) = block.kind.as_mut_gloss() {
             if gloss.numbered {
-                self.gloss_number += 1;
-                gloss.number = self.gloss_number;
+                let (last, used) = if self.shared_numbers {
+                    (&mut self.table_number, &mut self.table_numbers)
+                } else {
+                    (&mut self.gloss_number, &mut self.gloss_numbers)
+                };
+                gloss.number = assign_number(last, used, gloss.explicit_number).ok_or(
+                    ErrorKind::Number("gloss", gloss.explicit_number.unwrap_or(0)),
+                )?;
             }
             self.glosses.push(idx);
         }
-        if block.common.id.is_empty() {
-            block.common.id = format!("__no-id-{}", self.noid_index);
+        match block.kind.as_group() {
+            Some(Group::Start(start)) => self.open_groups.push(start.columns.is_some()),
+            Some(Group::End { .. }) => {
+                self.open_groups.pop();
+            }
+            None => {}
+        }
+        if noid {
             self.noid_index += 1;
         }
-        let id = block.common.id.clone();
-        match self.ids.entry(id) {
-            Entry::Occupied(e) => return Err(ErrorKind::Id(e.key().clone()).into()),
-            Entry::Vacant(e) => e.insert(idx),
-        };
+        self.citations
+            .extend(citations.into_iter().map(|(id, i)| (id, (idx, i))));
+        self.list_items
+            .extend(list_items.into_iter().map(|(id, path)| (id, (idx, path))));
+        if record_id {
+            self.ids.insert(block.common.id.clone(), idx);
+        }
+        self.input_warnings.extend(warnings);
+        if generated_id {
+            self.generated_ids.insert(idx);
+        }
+        self.add_footnotes(&mut *block.kind, idx)?;
+        if let Some(contents) = block.kind.as_contents() {
+            if contents.document_title && contents.root.is_none() && self.title_id.is_none() {
+                // the title isn't chosen by the author, so it is given a suffix if it is used
+                let mut id = TITLE_ID.to_owned();
+                claim_id(DuplicateIds::Suffix, &mut id, &|id| self.is_id_used(id))?;
+                self.reserved_ids.insert(id.clone());
+                self.title_id = Some(id);
+            }
+        }
         self.blocks.push(block);
         Ok(())
     }
 
+    /// Numbers the footnotes in the given block, which will be at the given index into the
+    /// `blocks` field, continuing on from the footnotes already in the document, and records their
+    /// text and IDs.
+    ///
+    /// The IDs are given a suffix if they are already used, since they aren't chosen by the author.
+    fn add_footnotes(&mut self, block: &mut dyn BlockType, idx: usize) -> EResult<()> {
+        let start = self.footnotes.len();
+        let mut number = start;
+        block.visit_text_mut(&mut |text| {
+            text.visit_mut(&mut |inline| {
+                if let InlineType::Footnote(footnote) = &mut inline.kind {
+                    number += 1;
+                    footnote.number = number;
+                }
+            })
+        });
+        if number == start {
+            return Ok(());
+        }
+        // the text is recorded after numbering, so that footnotes nested in other footnotes have
+        // their numbers
+        let mut texts = Vec::new();
+        block.visit_text(&mut |text| {
+            text.visit(&mut |inline| {
+                if let InlineType::Footnote(footnote) = &inline.kind {
+                    texts.push(footnote.text.clone());
+                }
+            })
+        });
+        for (text, number) in texts.into_iter().zip(start + 1..) {
+            let mut id = format!("{}{}", self.id_prefix("footnote", "fn-"), number);
+            claim_id(DuplicateIds::Suffix, &mut id, &|id| self.is_id_used(id))?;
+            self.reserved_ids.insert(id.clone());
+            let mut ref_id = format!("{}{}", self.id_prefix("footnoteref", "fnref-"), number);
+            claim_id(DuplicateIds::Suffix, &mut ref_id, &|id| self.is_id_used(id))?;
+            self.reserved_ids.insert(ref_id.clone());
+            self.footnotes.push(FootnoteEntry {
+                block: idx,
+                text,
+                id,
+                ref_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Appends the blocks of another document to this one.
+    ///
+    /// Automatically generated IDs are generated anew, and sections, tables, and glosses are
+    /// numbered continuing on from the blocks already in this document. Document control blocks
+    /// from `other` are applied as though they appeared at the end of this document, except that
+    /// imports are not repeated, since the imported blocks are already part of `other`.
+    /// Replacements defined in `other` take precedence over those defined in this document.
+    pub fn merge(&mut self, other: Document) -> Result<()> {
+        let Document {
+            blocks,
+            mut replacements,
+            generated_ids,
+            ..
+        } = other;
+        for (idx, mut block) in blocks.into_iter().enumerate() {
+            if let Some(heading) = block.kind.as_mut_heading() {
+                if heading.is_filler() {
+                    // `add_heading` will insert filler headings where they are needed
+                    continue;
+                }
+                heading.reset();
+            }
+            if let Some(DocumentControl::Import(_)) = block.kind.as_control() {
+                continue;
+            }
+            if generated_ids.contains(&idx) {
+                block.common.id.clear();
+            }
+            self.add_block(block)?;
+        }
+        self.replacements.update(&mut replacements);
+        Ok(())
+    }
+
     fn control(&mut self, control: &DocumentControl) -> EResult<()> {
         match control {
             DocumentControl::Title(text) => {
                 self.title.get_or_insert(text.clone());
             }
+            DocumentControl::SharedNumbers => {
+                self.shared_numbers = true;
+            }
+            DocumentControl::AutoTitle => {
+                self.auto_title = true;
+            }
             DocumentControl::Author(text) => {
                 self.author.get_or_insert(text.clone());
             }
@@ -109,25 +696,183 @@ impl Document {
             DocumentControl::Lang(text) => {
                 self.lang.get_or_insert(text.clone());
             }
+            DocumentControl::ReferencePrefixes(prefixes) => {
+                for (kind, prefix) in prefixes {
+                    self.reference_prefixes.insert(kind.clone(), prefix.clone());
+                }
+            }
+            DocumentControl::AriaLabels(labels) => {
+                for (kind, label) in labels {
+                    self.aria_labels.insert(kind.clone(), label.clone());
+                }
+            }
+            DocumentControl::NumberStyles(styles) => {
+                self.number_styles.extend(styles.iter().cloned());
+            }
+            DocumentControl::IdPrefixes(prefixes) => {
+                for (kind, prefix) in prefixes {
+                    let mut raw = String::new();
+                    self.push_raw_text(prefix, &mut raw);
+                    self.id_prefixes.insert(kind.clone(), raw);
+                }
+            }
+            DocumentControl::Abbreviations(abbreviations) => {
+                for (abbreviation, meaning) in abbreviations {
+                    self.abbreviations
+                        .insert(abbreviation.clone(), meaning.clone());
+                }
+            }
+            DocumentControl::Undefined(undefined) => {
+                self.undefined.get_or_insert(*undefined);
+            }
+            DocumentControl::Formatting(formatting) => {
+                self.formatting.get_or_insert(*formatting);
+            }
+            DocumentControl::Doctype(doctype) => {
+                self.doctype.get_or_insert(doctype.clone());
+            }
+            DocumentControl::Theme(theme) => {
+                self.theme.get_or_insert(theme.clone());
+            }
+            DocumentControl::AutoToc(auto_toc) => {
+                if self.auto_toc.is_none() {
+                    self.auto_toc = Some(*auto_toc);
+                    match auto_toc {
+                        AutoToc::AfterTitle => {
+                            // the ID isn't chosen by the author, so it is given a suffix if it is
+                            // used
+                            let mut id = AUTO_TOC_ID.to_owned();
+                            claim_id(DuplicateIds::Suffix, &mut id, &|id| self.is_id_used(id))?;
+                            self.reserved_ids.insert(id.clone());
+                            self.auto_toc_id = Some(id);
+                        }
+                        AutoToc::None => {
+                            // tables of contents aren't written, so they can't be referred to
+                            let blocks = &self.blocks;
+                            self.ids
+                                .retain(|_, idx| blocks[*idx].kind.as_contents().is_none());
+                        }
+                    }
+                }
+            }
+            DocumentControl::DefaultBlock(default_block) => {
+                if self.safe && *default_block == DefaultBlock::Raw {
+                    return Err(ErrorKind::UnsafeHtml("`:defaultblock: raw`".into()).into());
+                }
+                self.default_block = *default_block;
+            }
+            DocumentControl::Define(variable) => {
+                self.define(variable.clone());
+            }
+            // handled in `add_block`, since they affect whether the blocks after them are added
+            DocumentControl::If { .. } | DocumentControl::EndIf => {}
+            DocumentControl::VariantSeparator(separator) => {
+                self.variant_separator.get_or_insert(separator.clone());
+            }
+            DocumentControl::MaxHeading(max_heading) => {
+                self.max_heading.get_or_insert(*max_heading);
+            }
+            DocumentControl::BaseUrl(base_url) => {
+                self.base_url.get_or_insert(base_url.clone());
+            }
+            DocumentControl::HtmlAttributes(attributes) => {
+                for (name, _) in attributes {
+                    if name.is_empty()
+                        || !name
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+                    {
+                        return Err(ErrorKind::AttributeName(name.clone()).into());
+                    }
+                    if self.safe && name.to_ascii_lowercase().starts_with("on") {
+                        return Err(ErrorKind::UnsafeHtml(format!("attribute `{}`", name)).into());
+                    }
+                }
+                self.html_attributes.extend(attributes.iter().cloned());
+            }
             DocumentControl::Import(text) => {
                 let mut filename = Vec::new();
                 text.write_inline_plain(&mut filename, self)
                     .expect("Writing to `Vec<u8>` shouldn't fail");
                 let filename =
                     String::from_utf8(filename).expect("`Text` should always write valid utf-8");
-                let file = Path::new(filename.trim())
+                if self.safe {
+                    return Err(ErrorKind::FileAccess(filename.trim().into()).into());
+                }
+                let file = match Path::new(filename.trim())
                     .canonicalize()
                     .and_then(File::open)
-                    .context(ErrorKind::FileNotFound(filename))?;
+                {
+                    Ok(file) => file,
+                    Err(_) if self.lenient_imports => {
+                        let filename = filename.trim().to_owned();
+                        self.input_warnings
+                            .push(ErrorKind::FileNotFound(filename.clone()));
+                        return Ok(self.add_block(MissingImport { filename }.into())?);
+                    }
+                    Err(err) => {
+                        return Err(err).with_context(|| ErrorKind::FileNotFound(filename.clone()))
+                    }
+                };
                 let mut input = Input::new(BufReader::new(file));
-                while let Some(block) = input.next_block()?.parse()? {
-                    self.add_block(block)?;
-                }
+                input.set_bidi_controls(self.bidi_controls);
+                input.set_debug_params(self.debug_params);
+                let mut blocks = Vec::new();
+                input.set_default_block(self.default_block);
+                let mut read_blocks = || -> EResult<()> {
+                    while let Some(block) = input.next_block()?.parse()? {
+                        // the blocks are added after they're all read, so `:defaultblock:` has to
+                        // be applied here
+                        if let Some(DocumentControl::DefaultBlock(default_block)) =
+                            block.kind.as_control()
+                        {
+                            input.set_default_block(*default_block);
+                        }
+                        blocks.push(block);
+                    }
+                    Ok(())
+                };
+                read_blocks().context(ErrorKind::Source(filename.trim().into()))?;
+                self.input_warnings.extend(input.take_warnings());
+                self.add_imported_blocks(blocks)
+                    .context(ErrorKind::Source(filename.trim().into()))?;
             }
         }
         Ok(())
     }
 
+    /// Adds the blocks of an imported file.
+    fn add_imported_blocks(&mut self, blocks: Vec<Block>) -> Result<()> {
+        // the imported file's `lang` applies only to its own blocks, so they're wrapped in
+        // an element with that `lang` instead of setting the `lang` of the whole document.
+        let lang = blocks
+            .iter()
+            .find_map(|block| match block.kind.as_control() {
+                Some(DocumentControl::Lang(lang)) => Some(lang.clone()),
+                _ => None,
+            });
+        let scoped = lang.is_some();
+        if let Some(lang) = lang {
+            self.add_block(LangScope::Start(lang).into())?;
+        }
+        // `:if:` blocks in the imported file have to be ended in that file. the `:import:` block is
+        // only added if it's included, so the outer conditions can be set aside until the end.
+        let outer_conditions = std::mem::take(&mut self.conditions);
+        let result = blocks
+            .into_iter()
+            .filter(|block| {
+                !scoped || !matches!(block.kind.as_control(), Some(DocumentControl::Lang(_)))
+            })
+            .try_for_each(|block| self.add_block(block))
+            .and_then(|()| self.check_conditions_closed());
+        self.conditions = outer_conditions;
+        result?;
+        if scoped {
+            self.add_block(LangScope::End.into())?;
+        }
+        Ok(())
+    }
+
     fn add_heading(
         &mut self,
         heading: &mut dyn HeadingLike,
@@ -163,7 +908,11 @@ impl Document {
         if heading.numbered() {
             heading.push_number(self.get_section_list(curr).last_child_number + 1);
             if common.id.is_empty() {
-                common.id = format!("sec-{}", heading.number().iter().format("-"));
+                common.id = format!(
+                    "{}{}",
+                    self.id_prefix("section", "sec-"),
+                    heading.number().iter().format("-")
+                );
             }
         }
         self.get_mut_section_list(curr)
@@ -171,30 +920,306 @@ impl Document {
         Ok(idx)
     }
 
+    /// Adds a function which the HTML of the document is passed through before it is written,
+    /// after any which were already added, for post-processing such as minifying the HTML or
+    /// rewriting links.
+    ///
+    /// When there are post-processors, the whole document (or file of a split document) is
+    /// rendered before any of it is written.
+    pub fn add_post_processor(&mut self, f: impl Fn(String) -> String + 'static) {
+        self.post_processors.0.push(Box::new(f));
+    }
+
+    /// Passes the rendered HTML through the post-processors, and writes the result.
+    fn write_post_processed(&self, w: &mut impl Write, html: Vec<u8>) -> Result<()> {
+        let html = String::from_utf8(html).expect("documents should always write valid utf-8");
+        let html = self.post_processors.0.iter().fold(html, |html, f| f(html));
+        w.write_all(html.as_bytes())
+            .context(ErrorKind::WriteIoPostProcessed)?;
+        Ok(())
+    }
+
+    /// Wraps the writer the document is written to, to escape bidirectional control characters if
+    /// they are to be escaped.
+    fn output_writer<'a, W: Write>(&self, w: &'a mut W) -> CharRefWriter<&'a mut W> {
+        let encode = if self.bidi_controls == BidiControls::Escape {
+            BidiControls::is_control
+        } else {
+            |_| false
+        };
+        CharRefWriter::new(w, encode)
+    }
+
     /// Writes the blocks as HTML.
-    pub fn write(&self, w: &mut impl Write) -> EResult<()> {
+    pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        let w = &mut self.output_writer(w);
+        if !self.post_processors.0.is_empty() {
+            let mut html = Vec::new();
+            self.write_unprocessed(&mut html)?;
+            return self.write_post_processed(w, html);
+        }
+        self.write_unprocessed(w)
+    }
+
+    /// Writes the blocks and their footnotes as a fragment of HTML, without the head and tail of
+    /// the document, for previewing a snippet of markup.
+    pub fn write_fragment(&self, w: &mut impl Write) -> Result<()> {
+        let w = &mut self.output_writer(w);
+        if !self.post_processors.0.is_empty() {
+            let mut html = Vec::new();
+            self.write_fragment_unprocessed(&mut html)?;
+            return self.write_post_processed(w, html);
+        }
+        self.write_fragment_unprocessed(w)
+    }
+
+    /// Writes the blocks and their footnotes as a fragment of HTML, without post-processing.
+    fn write_fragment_unprocessed(&self, w: &mut impl Write) -> Result<()> {
+        self.write_blocks(w, 0..self.blocks.len())?;
+        self.write_footnotes(w).context(ErrorKind::WriteIoTail)?;
+        Ok(())
+    }
+
+    /// Writes the blocks as HTML, without post-processing.
+    fn write_unprocessed(&self, w: &mut impl Write) -> Result<()> {
+        self.write_head(w).context(ErrorKind::WriteIoHead)?;
+        self.write_auto_toc(w)?;
+        self.write_blocks(w, 0..self.blocks.len())?;
+        self.write_footnotes(w).context(ErrorKind::WriteIoTail)?;
+        self.write_footer(w).context(ErrorKind::WriteIoTail)?;
+        self.write_tail(w).context(ErrorKind::WriteIoTail)?;
+        Ok(())
+    }
+
+    /// Renders the block with the specified ID on its own, or returns `None` if there is no such
+    /// block, for hosts which re-render one block at a time, such as a live preview.
+    ///
+    /// References are resolved using the whole document, so references to blocks which haven't
+    /// been added to the document yet won't resolve.
+    pub fn render_block(&self, id: &str) -> Option<Result<String>> {
+        let idx = self.get_index(id)?;
+        let Block { kind, common } = &self.blocks[idx];
+        let mut out = Vec::new();
+        self.current_block.set(Some(idx));
+        let result = kind.write(&mut out, common, self);
+        self.current_block.set(None);
+        Some(
+            result
+                .context(ErrorKind::WriteIo(common.start_line))
+                .map(|()| String::from_utf8(out).expect("blocks should always write valid utf-8"))
+                .map_err(Error::from),
+        )
+    }
+
+    /// Splits the document into multiple files: one for each section whose heading is at most the
+    /// given level, and an index file, `index.html`, containing the blocks before the first such
+    /// section and a list of links to the other files.
+    ///
+    /// Each section's file is named after the ID of its heading, with any characters other than
+    /// ASCII letters, digits, `-` and `_` replaced by `_`, so that the files are always written
+    /// inside the output directory. References to blocks in other files link to those files.
+    pub fn split_by_level(&mut self, level: usize) {
+        self.files = vec![OutputFile {
+            name: "index.html".into(),
+            start: 0,
+        }];
+        let mut names = HashSet::from([String::from("index")]);
+        for (idx, block) in self.blocks.iter().enumerate() {
+            if let Some(heading) = block.kind.as_heading() {
+                if heading.level() <= level && !heading.is_filler() {
+                    let mut base = block
+                        .common
+                        .id
+                        .chars()
+                        .map(|c| {
+                            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                                c
+                            } else {
+                                '_'
+                            }
+                        })
+                        .collect::<String>();
+                    if base.is_empty() {
+                        base = format!("file-{}", self.files.len());
+                    }
+                    let mut name = base.clone();
+                    let mut suffix = 2;
+                    while !names.insert(name.clone()) {
+                        name = format!("{}-{}", base, suffix);
+                        suffix += 1;
+                    }
+                    self.files.push(OutputFile {
+                        name: format!("{}.html", name),
+                        start: idx,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Gets the files the document is split into, or an empty slice if it hasn't been split.
+    pub fn files(&self) -> &[OutputFile] {
+        &self.files
+    }
+
+    /// Writes the blocks in the specified file of a split document as HTML.
+    ///
+    /// Panics if the document hasn't been split, or the file doesn't exist.
+    pub fn write_file(&self, w: &mut impl Write, file: usize) -> Result<()> {
+        let w = &mut self.output_writer(w);
+        if !self.post_processors.0.is_empty() {
+            let mut html = Vec::new();
+            self.write_file_unprocessed(&mut html, file)?;
+            return self.write_post_processed(w, html);
+        }
+        self.write_file_unprocessed(w, file)
+    }
+
+    /// Writes the blocks in the specified file of a split document as HTML, without
+    /// post-processing.
+    fn write_file_unprocessed(&self, w: &mut impl Write, file: usize) -> Result<()> {
+        let start = self.files[file].start;
+        let end = self
+            .files
+            .get(file + 1)
+            .map_or(self.blocks.len(), |next| next.start);
+        self.current_file.set(file);
         self.write_head(w).context(ErrorKind::WriteIoHead)?;
-        for Block { kind, common } in &self.blocks {
-            kind.write(w, common, self)
-                .context(ErrorKind::WriteIo(common.start_line))?;
+        if file == 0 {
+            self.write_auto_toc(w)?;
         }
+        self.write_blocks(w, start..end)?;
+        if file == 0 {
+            self.write_index(w).context(ErrorKind::WriteIoTail)?;
+        }
+        self.write_footnotes(w).context(ErrorKind::WriteIoTail)?;
+        self.write_footer(w).context(ErrorKind::WriteIoTail)?;
         self.write_tail(w).context(ErrorKind::WriteIoTail)?;
+        self.current_file.set(0);
+        Ok(())
+    }
+
+    /// Writes the given blocks, ending any groups and language scopes which they start but don't
+    /// end, and skipping the ends of those which they don't start, so that they can be written to
+    /// a file on their own.
+    fn write_blocks(&self, w: &mut impl Write, blocks: Range<usize>) -> EResult<()> {
+        let mut open_groups = 0_usize;
+        for idx in blocks {
+            let Block { kind, common } = &self.blocks[idx];
+            if kind.as_contents().is_some() && self.auto_toc == Some(AutoToc::None) {
+                continue;
+            }
+            let (start, end) = match (kind.as_group(), kind.as_lang_scope()) {
+                (Some(Group::Start(_)), _) | (_, Some(LangScope::Start(_))) => (true, false),
+                (Some(Group::End { .. }), _) | (_, Some(LangScope::End)) => (false, true),
+                _ => (false, false),
+            };
+            if start {
+                open_groups += 1;
+            } else if end {
+                match open_groups.checked_sub(1) {
+                    Some(n) => open_groups = n,
+                    None => continue,
+                }
+            }
+            self.current_block.set(Some(idx));
+            let result = kind.write(w, common, self);
+            self.current_block.set(None);
+            result.context(ErrorKind::WriteIo(common.start_line))?;
+        }
+        for _ in 0..open_groups {
+            writeln!(w, "</div>\n").context(ErrorKind::WriteIoTail)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the table of contents generated by `:autotoc: after-title`, if it is set.
+    fn write_auto_toc(&self, w: &mut impl Write) -> EResult<()> {
+        if let Some(id) = &self.auto_toc_id {
+            let common = BlockCommon {
+                id: id.clone(),
+                ..Default::default()
+            };
+            Contents::new()
+                .write(w, &common, self)
+                .context(ErrorKind::WriteIoHead)?;
+        }
         Ok(())
     }
 
+    /// Writes the list of links to the files of a split document.
+    fn write_index(&self, w: &mut impl Write) -> IoResult<()> {
+        write!(w, "<ul")?;
+        self.write_class(w, self.default_class("split-index"))?;
+        writeln!(w, ">")?;
+        for file in &self.files[1..] {
+            write!(w, "<li><a href=\"{}\">", html::Encoder(&file.name))?;
+            self.get_heading(file.start).title().write_inline(w, self)?;
+            writeln!(w, "</a></li>")?;
+        }
+        writeln!(w, "</ul>\n")
+    }
+
+    /// Gets the URL of the block, bibliography entry, or list item with the specified ID, relative
+    /// to the file currently being written.
+    pub fn href(&self, id: &str) -> String {
+        let idx = self.containing_block(id);
+        // the index of the file containing the block, where the files start in order.
+        let file = idx.map(|idx| {
+            self.files
+                .partition_point(|f| f.start <= idx)
+                .saturating_sub(1)
+        });
+        match file {
+            Some(file) if file != self.current_file.get() => {
+                format!("{}#{}", self.files[file].name, id)
+            }
+            _ => format!("#{}", id),
+        }
+    }
+
+    /// Gets the index into the `blocks` field of the block with the specified ID, or of the block
+    /// containing the bibliography entry or list item with that ID.
+    fn containing_block(&self, id: &str) -> Option<usize> {
+        self.get_index(id)
+            .or_else(|| self.citations.get(id).map(|&(idx, _)| idx))
+            .or_else(|| self.list_items.get(id).map(|&(idx, _)| idx))
+    }
+
+    /// Compares the position of the block, bibliography entry, or list item with the specified ID
+    /// to the position of the block currently being written, returning `Ordering::Less` if it
+    /// comes before that block.
+    ///
+    /// Returns `None` if there is no such ID, or if no block is being written.
+    pub fn relative_position(&self, id: &str) -> Option<Ordering> {
+        Some(self.containing_block(id)?.cmp(&self.current_block.get()?))
+    }
+
     fn write_head(&self, w: &mut impl Write) -> IoResult<()> {
-        writeln!(w, "<!doctype html>")?;
+        match self.doctype.as_deref() {
+            Some("none") => {}
+            Some(doctype) => writeln!(w, "<!doctype {}>", doctype)?,
+            None => writeln!(w, "<!doctype html>")?,
+        }
         write!(w, "<html")?;
         if let Some(lang) = &self.lang {
             write!(w, " lang=\"")?;
             lang.write_inline_plain(w, self)?;
-            writeln!(w, "\">")?;
-        } else {
-            writeln!(w, ">")?;
+            write!(w, "\"")?;
+        }
+        for (name, value) in &self.html_attributes {
+            write!(w, " {}=\"", html::Encoder(name))?;
+            value.write_inline_plain(w, self)?;
+            write!(w, "\"")?;
         }
+        writeln!(w, ">")?;
         writeln!(w, "<head>")?;
         writeln!(w, "<meta charset=\"utf-8\" />")?;
-        if let Some(title) = &self.title {
+        if !self.omit_generator {
+            writeln!(w, "<meta name=\"generator\" content=\"conlang_fmt\" />")?;
+        }
+        let fallback_title = self.fallback_title();
+        if let Some(title) = self.title.as_ref().or(fallback_title) {
             write!(w, "<title>")?;
             title.write_inline_plain(w, self)?;
             writeln!(w, "</title>")?;
@@ -211,75 +1236,2458 @@ impl Document {
         }
         for stylesheet in &self.stylesheets {
             write!(w, "<link rel=\"stylesheet\" type=\"text/css\" href=\"")?;
-            stylesheet.write_inline_plain(w, self)?;
+            if self.base_url.is_some() || !self.assets.is_empty() {
+                let mut url = String::new();
+                self.push_raw_text(stylesheet, &mut url);
+                let url = self.asset_url(url.trim());
+                write!(w, "{}", html::Encoder(&self.resolve_url(url)))?;
+            } else {
+                stylesheet.write_inline_plain(w, self)?;
+            }
             writeln!(w, "\" />")?;
         }
         writeln!(w, "</head>")?;
         writeln!(w, "<body>")?;
         if let Some(title) = &self.title {
-            write!(w, "<h1 class=\"title\">")?;
+            self.write_title_start(w)?;
             title.write_inline(w, self)?;
             writeln!(w, "</h1>")?;
+        } else if let Some(title) = fallback_title {
+            self.write_title_start(w)?;
+            title.write_inline_plain(w, self)?;
+            writeln!(w, "</h1>")?;
         }
         Ok(())
     }
 
-    fn write_tail(&self, w: &mut impl Write) -> IoResult<()> {
-        writeln!(w, "</body>")?;
-        writeln!(w, "</html>")?;
-        Ok(())
+    /// Writes the start tag of the title displayed at the top of the document.
+    fn write_title_start(&self, w: &mut impl Write) -> IoResult<()> {
+        write!(w, "<h1")?;
+        if let Some(id) = &self.title_id {
+            write!(w, " id=\"{}\"", html::Encoder(id))?;
+        }
+        self.write_class(w, self.default_class("title"))?;
+        write!(w, ">")
     }
 
-    /// Get a reference to the specified block.
-    pub fn get_block(&self, idx: usize) -> Option<&Block> {
-        self.blocks.get(idx)
+    /// Calls `f` with the text of the given field of the metadata, unless the field isn't set or
+    /// another field is being written.
+    pub fn with_metadata(
+        &self,
+        field: Metadata,
+        f: impl FnOnce(&Text) -> IoResult<()>,
+    ) -> IoResult<()> {
+        if self.writing_metadata.get() {
+            return Ok(());
+        }
+        let text = match field {
+            Metadata::Title => self.title().map(Cow::Borrowed),
+            Metadata::Author => self.author.as_ref().map(Cow::Borrowed),
+            Metadata::Date => self.timestamp.map(|timestamp| {
+                let (year, month, day) = civil_date(timestamp / 86400);
+                Cow::Owned(Text::from(format!("{:04}-{:02}-{:02}", year, month, day)))
+            }),
+        };
+        match text {
+            Some(text) => {
+                self.writing_metadata.set(true);
+                let result = f(&text);
+                self.writing_metadata.set(false);
+                result
+            }
+            None => Ok(()),
+        }
     }
 
-    /// Get a reference to the specified block as a heading.
-    ///
-    /// Panics if the specified block doesn't exist or isn't a heading.
-    pub fn get_heading(&self, block_index: usize) -> &dyn HeadingLike {
-        self.blocks[block_index].kind.as_heading().unwrap()
+    /// Sets the time the document was last updated, in seconds since the Unix epoch, which is
+    /// displayed in a footer.
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = Some(timestamp);
     }
 
-    /// Get a mutable reference to the specified block as a heading.
+    /// Sets whether reading files named in the document, such as by `:import:` or when copying
+    /// assets, is disabled, for rendering untrusted input.
     ///
-    /// Panics if the specified block doesn't exist or isn't a heading.
-    fn get_mut_heading(&mut self, block_index: usize) -> &mut dyn HeadingLike {
-        self.blocks[block_index].kind.as_mut_heading().unwrap()
+    /// If it is, attempting to read such a file is an error.
+    pub fn set_safe(&mut self, safe: bool) {
+        self.safe = safe;
     }
 
-    /// Get a reference to the children of the specified block, or the root section list if none is
-    /// specified.
-    ///
-    /// Panics if the specified block doesn't exist or isn't a heading.
-    pub fn get_section_list(&self, block_index: Option<usize>) -> &SectionList {
-        if let Some(idx) = block_index {
-            self.get_heading(idx).children()
-        } else {
-            &self.sections
-        }
+    /// Sets whether an imported file which doesn't exist is replaced by a placeholder, with a
+    /// warning, instead of being an error, for documents which are still being written.
+    pub fn set_lenient_imports(&mut self, lenient_imports: bool) {
+        self.lenient_imports = lenient_imports;
     }
 
-    /// Get a mutable reference to the children of the specified block, or the root section list if
-    /// none is specified.
-    ///
-    /// Panics if the specified block doesn't exist or isn't a heading.
-    fn get_mut_section_list(&mut self, block_index: Option<usize>) -> &mut SectionList {
-        if let Some(idx) = block_index {
-            self.get_mut_heading(idx).mut_children()
-        } else {
-            &mut self.sections
+    /// Sets whether blocks which can be referenced (headings, tables, and glosses) have a
+    /// `tabindex="-1"` attribute, so that following a link to one moves the keyboard focus to it,
+    /// for screen reader and keyboard users.
+    pub fn set_focusable_targets(&mut self, focusable_targets: bool) {
+        self.focusable_targets = focusable_targets;
+    }
+
+    /// Writes the attributes of the start tag of a block which can be referenced, other than its
+    /// ID and class.
+    pub fn write_target_attributes(&self, w: &mut dyn Write) -> IoResult<()> {
+        if self.focusable_targets {
+            write!(w, " tabindex=\"-1\"")?;
         }
+        Ok(())
     }
 
-    /// Gets a reference to the block with the specified ID.
-    pub fn get_id(&self, id: &str) -> Option<&Block> {
+    /// Sets whether to omit the `<meta name="generator">` tag identifying this program from the
+    /// head of the document.
+    pub fn set_omit_generator(&mut self, omit_generator: bool) {
+        self.omit_generator = omit_generator;
+    }
+
+    /// Sets whether to omit the built-in classes of elements, such as `toc` and `reference`, for
+    /// styling by element and structure alone. Classes given in the document are kept.
+    pub fn set_omit_default_classes(&mut self, omit_default_classes: bool) {
+        self.omit_default_classes = omit_default_classes;
+    }
+
+    /// Writes a `class` attribute with the given classes, preceded by a space.
+    ///
+    /// If built-in classes are omitted, the classes are separated by single spaces, and the
+    /// attribute is left out if there are none, rather than leaving the gaps of the omitted
+    /// classes.
+    pub fn write_class(&self, w: &mut dyn Write, class: &str) -> IoResult<()> {
+        if !self.omit_default_classes {
+            return write!(w, " class=\"{}\"", html::Encoder(class));
+        }
+        let classes = class.split_whitespace().join(" ");
+        if classes.is_empty() {
+            return Ok(());
+        }
+        write!(w, " class=\"{}\"", html::Encoder(&classes))
+    }
+
+    /// Returns the given built-in class, or an empty string if built-in classes are omitted.
+    pub fn default_class<'a>(&self, class: &'a str) -> &'a str {
+        if self.omit_default_classes {
+            ""
+        } else {
+            class
+        }
+    }
+
+    /// Marks the section with the given ID as the current section in tables of contents.
+    pub fn set_active_section(&mut self, id: String) -> Result<()> {
+        match self.get_id(&id) {
+            Some(block) if block.kind.as_heading().is_some() => {
+                self.active_section = Some(id);
+                Ok(())
+            }
+            _ => Err(ErrorKind::ActiveSection(id).into()),
+        }
+    }
+
+    /// Gets the ID of the section marked as the current section.
+    pub fn active_section(&self) -> Option<&String> {
+        self.active_section.as_ref()
+    }
+
+    fn write_footer(&self, w: &mut impl Write) -> IoResult<()> {
+        if let Some(timestamp) = self.timestamp {
+            let (year, month, day) = civil_date(timestamp / 86400);
+            let seconds = timestamp % 86400;
+            write!(w, "<footer")?;
+            self.write_class(w, self.default_class("timestamp"))?;
+            writeln!(
+                w,
+                ">Last updated \
+                <time datetime=\"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z\">{:04}-{:02}-{:02}</time>\
+                </footer>",
+                year,
+                month,
+                day,
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                year,
+                month,
+                day,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Gets the title displayed at the top of the document, if it has one.
+    pub fn title(&self) -> Option<&Text> {
+        self.title.as_ref().or_else(|| self.fallback_title())
+    }
+
+    /// Gets the ID of the title displayed at the top of the document, if a table of contents links
+    /// to it.
+    pub fn title_id(&self) -> Option<&str> {
+        self.title_id.as_deref()
+    }
+
+    /// Gets the title of the first section, if the document has no title and `:autotitle:` is
+    /// set.
+    fn fallback_title(&self) -> Option<&Text> {
+        if self.title.is_some() || !self.auto_title {
+            return None;
+        }
+        let &first = self.sections.first()?;
+        let heading = self.get_heading(first);
+        if heading.is_filler() {
+            None
+        } else {
+            Some(heading.title())
+        }
+    }
+
+    /// Writes the text of the footnotes in the file currently being written, each with a link back
+    /// to where it is referenced.
+    fn write_footnotes(&self, w: &mut impl Write) -> IoResult<()> {
+        let file = self.current_file.get();
+        let blocks = match self.files.get(file) {
+            Some(start) => {
+                start.start
+                    ..self
+                        .files
+                        .get(file + 1)
+                        .map_or(self.blocks.len(), |f| f.start)
+            }
+            None => 0..self.blocks.len(),
+        };
+        let mut footnotes = self
+            .footnotes
+            .iter()
+            .enumerate()
+            .filter(|(_, footnote)| blocks.contains(&footnote.block))
+            .peekable();
+        if footnotes.peek().is_none() {
+            return Ok(());
+        }
+        write!(w, "<section")?;
+        self.write_class(w, self.default_class("footnotes"))?;
+        writeln!(w, ">\n<ol>")?;
+        for (i, footnote) in footnotes {
+            let number = i + 1;
+            write!(
+                w,
+                "<li id=\"{}\" value=\"{}\">",
+                html::Encoder(&footnote.id),
+                number
+            )?;
+            // footnotes nested in this one are referenced from here, as though it were part of
+            // the block containing it
+            self.current_block.set(Some(footnote.block));
+            let result = footnote.text.write_inline(w, self);
+            self.current_block.set(None);
+            result?;
+            write!(w, " <a")?;
+            self.write_class(w, self.default_class("footnote-backref"))?;
+            writeln!(
+                w,
+                " href=\"#{}\" aria-label=\"Back to reference {}\">&#x21a9;</a></li>",
+                html::Encoder(&footnote.ref_id),
+                number
+            )?;
+        }
+        writeln!(w, "</ol>\n</section>")
+    }
+
+    fn write_tail(&self, w: &mut impl Write) -> IoResult<()> {
+        writeln!(w, "</body>")?;
+        writeln!(w, "</html>")?;
+        Ok(())
+    }
+
+    /// Replaces the contents of each run of plain text in the document (including the title and
+    /// the definitions of replacements) with the result of calling `f` on it.
+    ///
+    /// IDs, URLs, parameters, and gloss abbreviations are not changed.
+    pub fn map_text(&mut self, mut f: impl FnMut(&str) -> String) {
+        let mut map = |text: &mut Text| {
+            text.visit_mut(&mut |inline| {
+                if let InlineType::Text(s) = &mut inline.kind {
+                    *s = f(s);
+                }
+            })
+        };
+        if let Some(title) = &mut self.title {
+            map(title);
+        }
+        for replacement in self.replacements.replacements.values_mut() {
+            map(replacement);
+        }
+        for block in &mut self.blocks {
+            block.kind.visit_text_mut(&mut map);
+        }
+    }
+
+    /// Checks the URLs of the links in the document, returning a warning for each malformed URL.
+    pub fn check_links(&self) -> Vec<ErrorKind> {
+        let mut warnings = Vec::new();
+        for block in &self.blocks {
+            block.kind.visit_text(&mut |text| {
+                text.visit(&mut |inline| {
+                    if let InlineType::Link(link) = &inline.kind {
+                        if let Err(warning) = link.check_url() {
+                            warnings.push(warning);
+                        }
+                    }
+                })
+            });
+        }
+        warnings
+    }
+
+    /// Copies the local files used by the document (its stylesheets and the audio files of spans)
+    /// into the given directory, so that it can be deployed as a whole, and rewrites their URLs to
+    /// refer to the copies.
+    ///
+    /// Relative paths are resolved from the current directory. Files with the same name are given
+    /// distinct names by appending a number (e.g. `style-1.css`). URLs with a scheme, such as
+    /// `https://example.com/style.css`, and paths from the root of the site, such as
+    /// `/style.css`, are left unchanged.
+    pub fn copy_assets(&mut self, dir: &Path) -> Result<()> {
+        let mut urls = Vec::new();
+        for stylesheet in &self.stylesheets {
+            let mut url = String::new();
+            self.push_raw_text(stylesheet, &mut url);
+            urls.push(url.trim().to_owned());
+        }
+        for block in &self.blocks {
+            block.kind.visit_text(&mut |text| {
+                text.visit(&mut |inline| {
+                    if let Some(audio) = &inline.common.audio {
+                        urls.push(audio.clone());
+                    }
+                })
+            });
+        }
+        let mut copies = HashMap::<PathBuf, String>::new();
+        let mut names = self.assets.values().cloned().collect::<HashSet<_>>();
+        for url in urls {
+            if self.assets.contains_key(&url) || !is_local_path(&url) {
+                continue;
+            }
+            if self.safe {
+                return Err(ErrorKind::FileAccess(url).into());
+            }
+            let source = Path::new(&url)
+                .canonicalize()
+                .with_context(|| ErrorKind::FileNotFound(url.clone()))?;
+            let name = match copies.get(&source) {
+                Some(name) => name.clone(),
+                None => {
+                    let name = unique_file_name(&source, &names);
+                    let dest = dir.join(&name);
+                    fs::copy(&source, &dest).with_context(|| {
+                        ErrorKind::FileCreate(dest.to_string_lossy().into_owned())
+                    })?;
+                    names.insert(name.clone());
+                    copies.insert(source, name.clone());
+                    name
+                }
+            };
+            self.assets.insert(url, name);
+        }
+        Ok(())
+    }
+
+    /// Gets the URL of the given local file relative to the output, which is the name of its copy
+    /// if it has been copied with `copy_assets`, or the URL unchanged otherwise.
+    pub fn asset_url<'a>(&'a self, url: &'a str) -> &'a str {
+        self.assets.get(url).map_or(url, String::as_str)
+    }
+
+    /// Lists the `:todo:` notes in the document, with the line on which the block containing each
+    /// one starts.
+    pub fn todos(&self) -> Vec<(usize, String)> {
+        let mut todos = Vec::new();
+        for block in &self.blocks {
+            block.kind.visit_text(&mut |text| {
+                text.visit(&mut |inline| {
+                    if let InlineType::Todo(inner) = &inline.kind {
+                        let mut todo = String::new();
+                        self.push_raw_text(inner, &mut todo);
+                        todos.push((block.common.start_line, todo));
+                    }
+                })
+            });
+        }
+        todos
+    }
+
+    /// Counts the occurrences of each word in the conlang spans (spans with the class `conlang`) of
+    /// the document.
+    pub fn conlang_frequencies(&self) -> HashMap<String, usize> {
+        let mut frequencies = HashMap::new();
+        for block in &self.blocks {
+            block.kind.visit_text(&mut |text| {
+                text.visit(&mut |inline| {
+                    if let Inline {
+                        kind: InlineType::Span(inner),
+                        common,
+                    } = inline
+                    {
+                        if common.has_class("conlang") {
+                            let mut words = String::new();
+                            self.push_raw_text(inner, &mut words);
+                            for word in words.split_whitespace() {
+                                *frequencies.entry(word.into()).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                })
+            });
+        }
+        frequencies
+    }
+
+    /// Appends the unformatted text of the given `Text` to `buffer`, expanding replacements but
+    /// ignoring references.
+    fn push_raw_text(&self, text: &Text, buffer: &mut String) {
+        text.visit(&mut |inline| match &inline.kind {
+            InlineType::Text(s) | InlineType::GlossAbbreviation(s) => buffer.push_str(s),
+            InlineType::Replace(key) => {
+                if let Some(replacement) = self.get_replacement(key) {
+                    self.push_raw_text(replacement, buffer);
+                }
+            }
+            _ => {}
+        });
+    }
+
+    /// Get a reference to the specified block.
+    pub fn get_block(&self, idx: usize) -> Option<&Block> {
+        self.blocks.get(idx)
+    }
+
+    /// Get a reference to the specified block as a heading.
+    ///
+    /// Panics if the specified block doesn't exist or isn't a heading.
+    pub fn get_heading(&self, block_index: usize) -> &dyn HeadingLike {
+        self.blocks[block_index].kind.as_heading().unwrap()
+    }
+
+    /// Get a mutable reference to the specified block as a heading.
+    ///
+    /// Panics if the specified block doesn't exist or isn't a heading.
+    fn get_mut_heading(&mut self, block_index: usize) -> &mut dyn HeadingLike {
+        self.blocks[block_index].kind.as_mut_heading().unwrap()
+    }
+
+    /// Get a reference to the children of the specified block, or the root section list if none is
+    /// specified.
+    ///
+    /// Panics if the specified block doesn't exist or isn't a heading.
+    pub fn get_section_list(&self, block_index: Option<usize>) -> &SectionList {
+        if let Some(idx) = block_index {
+            self.get_heading(idx).children()
+        } else {
+            &self.sections
+        }
+    }
+
+    /// Get a mutable reference to the children of the specified block, or the root section list if
+    /// none is specified.
+    ///
+    /// Panics if the specified block doesn't exist or isn't a heading.
+    fn get_mut_section_list(&mut self, block_index: Option<usize>) -> &mut SectionList {
+        if let Some(idx) = block_index {
+            self.get_mut_heading(idx).mut_children()
+        } else {
+            &mut self.sections
+        }
+    }
+
+    /// Gets the index of the block with the specified ID.
+    pub fn get_index(&self, id: &str) -> Option<usize> {
+        self.ids.get(id).copied()
+    }
+
+    /// Gets a reference to the block with the specified ID.
+    pub fn get_id(&self, id: &str) -> Option<&Block> {
         self.ids.get(id).map(|&idx| &self.blocks[idx])
     }
 
+    /// Gets a reference to the bibliography entry with the specified ID.
+    pub fn get_citation(&self, id: &str) -> Option<&BibliographyEntry> {
+        self.citations
+            .get(id)
+            .map(|&(idx, entry)| &self.blocks[idx].kind.as_bibliography().unwrap().entries[entry])
+    }
+
+    /// Gets a reference to the list item with the specified ID.
+    pub fn get_list_item(&self, id: &str) -> Option<&ListItem> {
+        self.list_items.get(id).map(|(idx, path)| {
+            ListItem::get(&self.blocks[*idx].kind.as_list().unwrap().items, path)
+        })
+    }
+
+    /// Gets a referenceable block, bibliography entry, or list item with the specified ID.
+    pub fn get_referenceable(&self, id: &str) -> Option<&dyn Referenceable> {
+        match self.get_id(id) {
+            Some(block) => block.kind.as_referenceable(),
+            None => self
+                .get_citation(id)
+                .map(|entry| entry as &dyn Referenceable)
+                .or_else(|| {
+                    self.get_list_item(id)
+                        .map(|item| item as &dyn Referenceable)
+                }),
+        }
+    }
+
+    /// Gets the text preceding the number in references to the given kind of block, or `default`
+    /// if no prefix has been defined for that kind.
+    pub fn reference_prefix(&self, kind: &str, default: &str) -> Text {
+        match self.reference_prefixes.get(kind) {
+            Some(prefix) => prefix.clone(),
+            None => Text::from(default),
+        }
+    }
+
+    /// Gets the accessible label (the `aria-label` attribute) for the given kind of block, or
+    /// `default` if no label has been defined for that kind.
+    pub fn aria_label(&self, kind: &str, default: &str) -> Text {
+        match self.aria_labels.get(kind) {
+            Some(label) => label.clone(),
+            None => Text::from(default),
+        }
+    }
+
+    /// Gets the separator between variant forms, which is `~` if none has been defined.
+    pub fn variant_separator(&self) -> &str {
+        self.variant_separator.as_deref().unwrap_or("~")
+    }
+
+    /// Formats the number of a block of the given kind, in the style defined for that kind.
+    pub fn format_number(&self, kind: &str, number: usize) -> String {
+        self.number_styles
+            .get(kind)
+            .copied()
+            .unwrap_or_default()
+            .format(number)
+    }
+
+    /// Gets the prefix of automatically generated IDs for the given kind of block, or `default` if
+    /// no prefix has been defined for that kind.
+    fn id_prefix<'a>(&'a self, kind: &str, default: &'a str) -> &'a str {
+        self.id_prefixes.get(kind).map_or(default, String::as_str)
+    }
+
+    /// Gets the IDs of the text of the footnote with the given number and of the reference to it,
+    /// if the reference is being written where it appears in the document: in the block containing
+    /// it, and not in the text of a cross reference.
+    ///
+    /// Otherwise, such as in a table of contents, the reference shouldn't have an ID, so that the
+    /// IDs are only used once.
+    pub fn footnote_ids(&self, number: usize) -> Option<(&str, &str)> {
+        let footnote = self.footnotes.get(number.checked_sub(1)?)?;
+        if self.writing_reference.get() || self.current_block.get() != Some(footnote.block) {
+            return None;
+        }
+        Some((&footnote.id, &footnote.ref_id))
+    }
+
+    /// Whether the contents of a link are being written, in which case links can't be nested in
+    /// them.
+    pub fn writing_link(&self) -> bool {
+        self.writing_link.get()
+    }
+
+    /// Calls `f`, which writes the contents of a link, or of a cross reference if `reference` is
+    /// set, so that references to footnotes inside it aren't written as links.
+    pub fn in_link(&self, reference: bool, f: impl FnOnce() -> IoResult<()>) -> IoResult<()> {
+        let writing_link = self.writing_link.replace(true);
+        let writing_reference = self
+            .writing_reference
+            .replace(self.writing_reference.get() || reference);
+        let result = f();
+        self.writing_link.set(writing_link);
+        self.writing_reference.set(writing_reference);
+        result
+    }
+
+    /// Gets the meaning of the given gloss abbreviation.
+    pub fn get_abbreviation(&self, abbreviation: &str) -> Option<&Text> {
+        self.abbreviations.get(abbreviation)
+    }
+
+    /// Prepends the base URL of the document, if it has one, to the given URL, if it is relative.
+    ///
+    /// Absolute URLs, paths starting with `/`, and links to anchors in the same page are left
+    /// unchanged.
+    pub fn resolve_url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        match &self.base_url {
+            Some(base)
+                if !url.starts_with(['/', '#'])
+                    && url::Url::parse(url) == Err(url::ParseError::RelativeUrlWithoutBase) =>
+            {
+                if base.ends_with('/') {
+                    format!("{}{}", base, url).into()
+                } else {
+                    format!("{}/{}", base, url).into()
+                }
+            }
+            _ => url.into(),
+        }
+    }
+
+    /// Gets the class for the theme of the document, if it has one.
+    pub fn theme_class(&self) -> Option<String> {
+        self.theme.as_ref().map(|theme| format!("theme-{}", theme))
+    }
+
+    /// Gets how to display undefined references and replacements.
+    pub fn undefined(&self) -> Undefined {
+        self.undefined.unwrap_or_default()
+    }
+
+    /// Gets how emphasis, strong emphasis, italics, and bold are written.
+    pub fn formatting(&self) -> Formatting {
+        self.formatting.unwrap_or_default()
+    }
+
+    /// Gets the deepest level of heading displayed as a heading, if there is one.
+    pub fn max_heading(&self) -> Option<MaxHeading> {
+        self.max_heading
+    }
+
     /// Gets the replacement text for the given key.
     pub fn get_replacement(&self, key: &str) -> Option<&Text> {
         self.replacements.get(key)
     }
 }
+
+/// Assigns a number to a block, which is `explicit` if it is set, and otherwise the first number
+/// after `last` which hasn't been used. Returns `None` if `explicit` has already been used.
+fn assign_number(
+    last: &mut usize,
+    used: &mut HashSet<usize>,
+    explicit: Option<usize>,
+) -> Option<usize> {
+    let number = match explicit {
+        Some(number) => number,
+        None => {
+            *last += 1;
+            while used.contains(last) {
+                *last += 1;
+            }
+            *last
+        }
+    };
+    if used.insert(number) {
+        Some(number)
+    } else {
+        None
+    }
+}
+
+/// Whether the URL is a path to a local file, rather than an absolute URL or a link to an anchor.
+fn is_local_path(url: &str) -> bool {
+    !url.is_empty()
+        && !url.starts_with(['#', '?', '/'])
+        && url::Url::parse(url) == Err(url::ParseError::RelativeUrlWithoutBase)
+}
+
+/// Chooses a name for a copy of the given file which isn't one of the given names, by appending
+/// a number to the stem of the file name if necessary.
+fn unique_file_name(path: &Path, names: &HashSet<String>) -> String {
+    let name = path.file_name().map_or_else(
+        || "asset".into(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    if !names.contains(&name) {
+        return name;
+    }
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "asset".into(), |stem| stem.to_string_lossy());
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|i| format!("{}-{}{}", stem, i, extension))
+        .find(|name| !names.contains(name))
+        .unwrap()
+}
+
+/// Converts a number of days since the Unix epoch to a year, month, and day in the Gregorian
+/// calendar.
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // shift the epoch to 0000-03-01, so that leap days come at the end of each year
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::Location;
+
+    use super::*;
+
+    fn document(s: &str) -> Document {
+        let mut input = Input::new(s.as_bytes());
+        let mut document = Document::default();
+        while let Some(block) = input.next_block().unwrap().parse().unwrap() {
+            document.add_block(block).unwrap();
+        }
+        document
+    }
+
+    fn render(document: &Document) -> String {
+        let mut out = Vec::new();
+        document.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn merge_numbering() {
+        let mut first = document(":table:[id=first] First\n::|a\n\nSome text.\n\n# One");
+        let second = document(":table:[id=second] Second\n::|b\n\nMore text.\n\n# Two");
+        first.merge(second).unwrap();
+        let html = render(&first);
+        assert!(html.contains(
+            r#"<table id="first" class=""><caption><span class="table-heading-prefix">Table 1:"#
+        ));
+        assert!(html.contains(
+            r#"<table id="second" class=""><caption><span class="table-heading-prefix">Table 2:"#
+        ));
+        assert!(html.contains(r#"<h1 id="sec-2" class=" ">"#));
+    }
+
+    #[test]
+    fn merge_ids() {
+        let mut first = document("Some text.\n\nMore text.");
+        let second = document("Other text.\n\nYet more text.");
+        first.merge(second).unwrap();
+        assert_eq!(first.blocks.len(), 4);
+        assert_eq!(first.ids.len(), 4);
+        for idx in 0..4 {
+            let id = &first.blocks[idx].common.id;
+            assert_eq!(first.ids[id], idx);
+        }
+    }
+
+    #[test]
+    fn merge_duplicate_id() {
+        let mut first = document(":table:[id=foo] First\n::|a");
+        let second = document(":table:[id=foo] Second\n::|b");
+        let err = first.merge(second).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("foo".into()));
+    }
+
+    #[test]
+    fn duplicate_ids() {
+        let text = ":table:[id=t] First\n::|a\n\n:list:\n::[id=t] Item\n\n\
+            :table:[id=t] Second\n::|b\n\nSee :ref:[t].";
+
+        let mut document = Document::default();
+        let err = document.read(text.as_bytes(), None).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("t".into()));
+
+        let mut document = Document::default();
+        document.set_duplicate_ids(DuplicateIds::Warn);
+        document.read(text.as_bytes(), None).unwrap();
+        assert_eq!(
+            document.input_warnings(),
+            [ErrorKind::Id("t".into()), ErrorKind::Id("t".into())]
+        );
+        let html = render(&document);
+        assert_eq!(html.matches(r#"id="t""#).count(), 3);
+        assert!(html.contains(r##"See <a class="reference " href="#t">table 1</a>."##));
+
+        let mut document = Document::default();
+        document.set_duplicate_ids(DuplicateIds::Suffix);
+        document.read(text.as_bytes(), None).unwrap();
+        assert!(document.input_warnings().is_empty());
+        let html = render(&document);
+        assert!(html.contains(r#"<table id="t" "#));
+        assert!(html.contains(r#"<li id="t-2">"#));
+        assert!(html.contains(r#"<table id="t-3" "#));
+        assert!(html.contains(r##"See <a class="reference " href="#t">table 1</a>."##));
+
+        // the IDs of a block which fails to be added aren't recorded
+        for text in [
+            ":table:[id=t] First\n::|a\n\n:bibliography: Sources\n::[a] A\n::[t] T",
+            ":table:[id=t] First\n::|a\n\n:list:\n::[id=a] A\n::[id=t] T",
+            ":table:[id=t] First\n::|a\n\n:list:[id=t]\n::[id=a] A",
+        ] {
+            let mut document = Document::default();
+            let err = document.read(text.as_bytes(), None).unwrap_err();
+            assert_eq!(err.kind(), &ErrorKind::Id("t".into()));
+            assert!(!document.is_id_used("a"));
+        }
+    }
+
+    #[test]
+    fn reference_prefixes() {
+        let document = document(
+            ":refprefix:\n:table: Tbl.\\ \n:section: §\n\n# Intro\n\n:table:[id=t] Foo\n::|a\n\nSee :ref:[t] and :ref:[sec-1], and :ref:[g].\n\n:gloss:[id=g]\n:: a",
+        );
+        let html = render(&document);
+        assert!(html.contains(r#"Tbl. 1</a>"#));
+        assert!(html.contains(r#"§<span class=" secnum"></span>1.</a>"#));
+        assert!(html.contains(r#"gloss 1</a>"#));
+    }
+
+    #[test]
+    fn citation_reference() {
+        let document = document(":table:[id=t] Foo\n::|a\n\nSee :ref:[t, style=cite].");
+        let html = render(&document);
+        assert!(html.contains(
+            r##"See <a class="reference " href="#t"><sup class="citation">[1]</sup></a>."##
+        ));
+    }
+
+    #[test]
+    fn gloss_abbreviation() {
+        let document = document(":abbr:\n:NOM: nominative\n\nThe :gl:NOM: and :gl:ACC: cases.");
+        let html = render(&document);
+        assert!(html.contains(
+            r#"<span class="small-caps " title="nominative">NOM</span> and <span class="small-caps ">ACC</span>"#
+        ));
+    }
+
+    #[test]
+    fn bibliography_author_year() {
+        let document = document(
+            ":bibliography:[sort] References\n\
+            ::[smith, author=Smith, year=2001] Morphology\n\
+            ::[jones, author=Jones, year=1999] Syntax\n\n\
+            As shown by :cite:[smith], and later :cite:[jones].",
+        );
+        let html = render(&document);
+        assert!(html.contains(r##"<a class="reference " href="#smith">Smith 2001</a>"##));
+        assert!(html.contains(r##"<a class="reference " href="#jones">Jones 1999</a>"##));
+        let jones = html.find(r#"<li id="jones">"#).unwrap();
+        let smith = html.find(r#"<li id="smith">"#).unwrap();
+        assert!(jones < smith);
+    }
+
+    #[test]
+    fn bibliography_numbered() {
+        let document = document(
+            ":bibliography:[numbered] References\n\
+            ::[smith, author=Smith, year=2001] Morphology\n\
+            ::[jones, author=Jones, year=1999] Syntax\n\n\
+            As shown by :cite:[jones], and :ref:[smith].",
+        );
+        let html = render(&document);
+        assert!(html.contains(
+            r##"<a class="reference " href="#jones"><sup class="citation">[2]</sup></a>"##
+        ));
+        assert!(html.contains(
+            r##"<a class="reference " href="#smith"><sup class="citation">[1]</sup></a>"##
+        ));
+        assert!(html.contains(r#"<ol>"#));
+    }
+
+    #[test]
+    fn undefined_span() {
+        let document = document("See :ref:[foo] and :bar:.");
+        let html = render(&document);
+        assert!(html.contains(r#"<span class="undefined-reference">#foo</span>"#));
+        assert!(html.contains(r#"<span class="undefined-replace">:bar:</span>"#));
+    }
+
+    #[test]
+    fn undefined_text() {
+        let document = document(":undefined: text\n\nSee :ref:[foo] and :bar:.");
+        let html = render(&document);
+        assert!(html.contains("See #foo and :bar:."));
+        assert!(!html.contains(r##"href="#foo""##));
+    }
+
+    #[test]
+    fn undefined_error() {
+        let document = document(":undefined: error\n\nSee :ref:[foo].");
+        let err = document.write(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind().to_string(), "Reference to undefined ID foo");
+    }
+
+    #[test]
+    fn number_reference() {
+        let html = render(&document(
+            "# One\n\n##[id=sub] Sub\n\n:gloss:[id=g]\n::a\n\n\
+            See :numref:[g], :numref:[sub], and :ref:[g, style=number].",
+        ));
+        assert!(html.contains(
+            r##"See <a class="reference " href="#g">1</a>, <a class="reference " href="#sub">1.1</a>, and <a class="reference " href="#g">1</a>."##
+        ));
+
+        let unnumbered = document(":gloss:[id=g, nonumber]\n::a\n\nSee :numref:[g].");
+        let err = unnumbered.write(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.kind().to_string(),
+            ErrorKind::UnnumberedReference("g".into()).to_string()
+        );
+    }
+
+    #[test]
+    fn progress_callback() {
+        let source = "# One\n\nSome text.\n\n\n:gloss:\n::a\n";
+        let mut reports = Vec::new();
+        let document = Document::from_reader_with_progress(
+            source.as_bytes(),
+            Some(&mut |progress| reports.push(progress)),
+        )
+        .unwrap();
+        assert_eq!(
+            reports,
+            [
+                Progress {
+                    blocks: 1,
+                    bytes: 7
+                },
+                Progress {
+                    blocks: 2,
+                    bytes: 19
+                },
+                Progress {
+                    blocks: 3,
+                    bytes: source.len()
+                },
+            ]
+        );
+        assert_eq!(
+            render(&document),
+            render(&Document::from_reader(source.as_bytes()).unwrap())
+        );
+    }
+
+    #[test]
+    fn check_links() {
+        let document = document(
+            "See :link:[https://example.com/a?b\\=c], :link:[mailto:a@example.com], \
+            :link:[other.html#sec-1], :link:[#sec-2], and :link:[../up].\n\n\
+            But not _:link:[htp://example.com]_ or :link:[http://exa mple.com].",
+        );
+        assert_eq!(
+            document.check_links(),
+            [
+                ErrorKind::LinkUrl("htp://example.com".into()),
+                ErrorKind::LinkUrl("http://exa mple.com".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment() {
+        let html = render(&document(
+            "Word:comment:{from *notes*, see --> \\} here} here.",
+        ));
+        assert!(html.contains("<p>Word<!-- from *notes*, see - -> } here --> here. </p>"));
+    }
+
+    #[test]
+    fn default_block_raw() {
+        let document = Document::from_reader(
+            "Before *text*.\n\n:defaultblock: raw\n\n\
+            data *a*   :ref:[x]\n  indented line\n\n# Heading\n\n\
+            :defaultblock: paragraph\n\nAfter *text*."
+                .as_bytes(),
+        )
+        .unwrap();
+        let html = render(&document);
+        assert!(html.contains("<p>Before <em class=\" \">text</em>. </p>"));
+        assert!(html.contains("\ndata *a*   :ref:[x]\n  indented line\n\n"));
+        assert!(html.contains("Heading </h1>"));
+        assert!(html.contains("<p>After <em class=\" \">text</em>. </p>"));
+
+        let mut input = Input::new("a *b*\n c".as_bytes());
+        let mut block = input.next_block().unwrap();
+        block.set_default_block(DefaultBlock::Raw);
+        let block = block.parse().unwrap().unwrap();
+        let raw = format!("{:?}", block.kind);
+        assert_eq!(raw, r#"Raw { lines: ["a *b*", " c"] }"#);
+    }
+
+    #[test]
+    fn relative_references() {
+        let document = document(
+            "#[id=a] First\n\n\
+            See :relref:a: and :relref:b:[class=x], and :ref:[b, style=relative].\n\n\
+            #[id=b] Second :relref:b:\n\n:title: :relref:a:",
+        );
+        let html = render(&document);
+        assert!(html.contains(
+            "See <a class=\"reference \" href=\"#a\">above</a> and \
+            <a class=\"reference x\" href=\"#b\">below</a>, and \
+            <a class=\"reference \" href=\"#b\">below</a>."
+        ));
+        // in the element it refers to, or outside of any block
+        assert!(html.contains("Second <a class=\"reference \" href=\"#b\">section "));
+        assert!(html.contains("<title> section 1. </title>"));
+
+        let heading = document.render_block("b").unwrap().unwrap();
+        assert!(heading.contains("href=\"#b\">section "));
+    }
+
+    #[test]
+    fn document_metadata() {
+        let mut document = document(
+            ":title: A *Grammar*\n\n\
+            By :docauthor:[who] (:docdate:), :doctitle:\n\n\
+            :author: :doctitle:",
+        );
+        let html = render(&document);
+        assert!(html.contains(
+            "By <span class=\" who\"> <span class=\" \"></span> </span> (<span class=\" \"></span>), \
+            <span class=\" \"> A <em class=\" \">Grammar</em> </span>"
+        ));
+
+        document.set_timestamp(1_700_000_000);
+        assert!(render(&document).contains("(<span class=\" \">2023-11-14</span>)"));
+    }
+
+    #[test]
+    fn write_fragment() {
+        let document = document(":title: T\n\n:replace:\n:x: X\n\nA *b*:x:.");
+        let mut out = Vec::new();
+        document.write_fragment(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<p>A <em class=\" \">b</em><span class=\" \"> X</span>. </p>\n\n"
+        );
+    }
+
+    #[test]
+    fn write_fragment_footnotes() {
+        let document = document("A :fn:{note} b.");
+        let mut out = Vec::new();
+        document.write_fragment(&mut out).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("href=\"#fn-1\""));
+        assert!(html.contains("<li id=\"fn-1\" value=\"1\">"));
+        assert!(!html.contains("<body"));
+    }
+
+    #[test]
+    fn page_breaks() {
+        let html = render(&document(
+            "#[id=a, break-before=page] One\n\n\
+            :table:[id=t, break-after=avoid, x]\n::|a\n\n#[id=b] Two",
+        ));
+        assert!(html.contains("<h1 id=\"a\" class=\" break-before-page \">"));
+        assert!(html.contains("<h1 id=\"b\" class=\" \">"));
+        assert!(html.contains("class=\"x break-after-avoid"));
+
+        let err = Input::new("#[break-before=sometimes] One".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("break-before".into(), "sometimes".into())
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = ":if: teacher\n\nAnswers.\n\n:if: !short\n\nLong answers.\n\n:endif:\n\n:endif:\n\n\
+            :if: !teacher\n\nExercises.\n\n:endif:\n\n:define: short\n\n:if: short\n\nShort.\n\n:endif:";
+        let html = render(&document(source));
+        assert!(!html.contains("Answers."));
+        assert!(!html.contains("Long answers."));
+        assert!(html.contains("<p>Exercises. </p>"));
+        assert!(html.contains("<p>Short. </p>"));
+
+        let mut document = Document::default();
+        document.define("teacher");
+        document.read(source.as_bytes(), None).unwrap();
+        let html = render(&document);
+        assert!(html.contains("<p>Answers. </p>"));
+        assert!(html.contains("<p>Long answers. </p>"));
+        assert!(!html.contains("Exercises."));
+
+        let err = Document::from_reader(":if: a\n\n:endif:\n\n:endif:".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::EndIf(4));
+
+        let err =
+            Document::from_reader(":if: a\n\n:if: b\n\nText.\n\n:endif:".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnclosedIf(0));
+
+        let unclosed = temp_file("unclosed-if.txt", ":if: a\n\nImported.");
+        let err = Document::from_reader(format!(":import: {}\n\nAfter.", unclosed).as_bytes())
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::UnclosedIf(0));
+
+        let extra = temp_file("extra-endif.txt", "Imported.\n\n:endif:");
+        let err =
+            Document::from_reader(format!(":if: !a\n\n:import: {}\n\n:endif:", extra).as_bytes())
+                .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::EndIf(2));
+    }
+
+    #[test]
+    fn auto_toc() {
+        let source = ":autotoc: after-title\n\n# One\n\nText.\n\n:toc:[root=a] Part\n\n#[id=a] Two\n\n## Three";
+        let html = render(&document(source));
+        let auto = html.find("<nav id=\"toc\" class=\" toc\"").unwrap();
+        assert!(auto < html.find("One </h1>").unwrap());
+        assert!(html.contains("Table of Contents Part"));
+
+        let html = render(&document(&source.replace("after-title", "none")));
+        assert!(!html.contains("<nav"));
+
+        let html = render(&document("#[id=toc] One\n\n:autotoc: after-title"));
+        assert!(html.contains("<nav id=\"toc-2\" class=\" toc\""));
+        assert_eq!(html.matches("id=\"toc\"").count(), 1);
+        let err =
+            Document::from_reader(":autotoc: after-title\n\n#[id=toc] One".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("toc".into()));
+
+        for source in [
+            ":toc:[id=contents]\n\n:autotoc: none\n\nSee :ref:[contents].",
+            ":autotoc: none\n\n:toc:[id=contents]\n\nSee :ref:[contents].",
+        ] {
+            let html = render(&document(source));
+            assert!(html.contains(r#"<span class="undefined-reference">#contents</span>"#));
+        }
+
+        let err = Input::new(":autotoc: sometimes".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("autotoc".into(), "sometimes".into())
+        );
+    }
+
+    #[test]
+    fn link_titles() {
+        let html = render(&document(
+            "See :link:{the *Ethnologue* entry}[https://a.example, class=x], \
+            :link:[url=https://b.example, title=*B*], :link:{C}[https://c.example, title=D], \
+            and :link:[https://d.example].",
+        ));
+        assert!(html.contains(
+            "See <a class=\" x\" href=\"https://a.example\">the <em class=\" \">Ethnologue</em> \
+            entry</a>, <a class=\" \" href=\"https://b.example\">*B*</a>, \
+            <a class=\" \" href=\"https://c.example\">C</a>, \
+            and <a class=\" \" href=\"https://d.example\">https://d.example</a>."
+        ));
+    }
+
+    #[test]
+    fn focusable_targets() {
+        let mut document = document("#[id=a] One\n\nSee :ref:[a].\n\n:gloss:[id=g]\n::a");
+        assert!(!render(&document).contains("tabindex"));
+        document.set_focusable_targets(true);
+        let html = render(&document);
+        assert!(html.contains("<h1 id=\"a\" tabindex=\"-1\" class=\" \">"));
+        assert!(html.contains("id=\"g\" tabindex=\"-1\" "));
+        assert!(html.contains("<p>See <a class=\"reference \" href=\"#a\">"));
+    }
+
+    #[test]
+    fn footnotes() {
+        assert!(!render(&document("No notes.")).contains("footnotes"));
+
+        let document = document(
+            "A claim.:fn:{See *there*.:fn:{Nested.}} And:fn:{Second.}[class=x]\n\n\
+            # Sec\n\nMore:fn:{Third.}",
+        );
+        let html = render(&document);
+        assert!(html.contains(
+            r##"A claim.<sup class="footnote-ref "><a href="#fn-1" id="fnref-1">1</a></sup> And<sup class="footnote-ref x"><a href="#fn-3" id="fnref-3">3</a></sup>"##
+        ));
+        assert!(html.contains(
+            r##"<p>More<sup class="footnote-ref "><a href="#fn-4" id="fnref-4">4</a></sup> </p>"##
+        ));
+        assert!(html.contains(
+            "<section class=\"footnotes\">\n<ol>\n\
+            <li id=\"fn-1\" value=\"1\">See <em class=\" \">there</em>.\
+            <sup class=\"footnote-ref \"><a href=\"#fn-2\" id=\"fnref-2\">2</a></sup> \
+            <a class=\"footnote-backref\" href=\"#fnref-1\" aria-label=\"Back to reference 1\">&#x21a9;</a></li>\n\
+            <li id=\"fn-2\" value=\"2\">Nested. <a class=\"footnote-backref\" href=\"#fnref-2\" \
+            aria-label=\"Back to reference 2\">&#x21a9;</a></li>\n"
+        ));
+        assert!(html.contains("<li id=\"fn-4\" value=\"4\">Third. "));
+        assert!(html.contains("</ol>\n</section>\n</body>"));
+
+        // each file of a split document lists its own footnotes
+        let mut document = document;
+        document.split_by_level(1);
+        let mut out = Vec::new();
+        document.write_file(&mut out, 1).unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<li id=\"fn-4\" value=\"4\">"));
+        assert!(!html.contains("<li id=\"fn-1\""));
+    }
+
+    #[test]
+    fn quote() {
+        let html = render(&document(
+            ":quote:[cite=https://example.com/x, id=q, class=c] To be,\n  or not to be.\n\
+            :: That is the question.\n::[attribution] _Hamlet_",
+        ));
+        assert!(html.contains(
+            "<blockquote id=\"q\" class=\"quote c\" cite=\"https://example.com/x\">\n\
+            <p> To be, or not to be.</p>\n<p> That is the question.</p>\n\
+            <footer> <i class=\" \">Hamlet</i></footer>\n</blockquote>\n"
+        ));
+
+        let html = render(&document(":quote:\n::One\n::[class=x] Two"));
+        assert!(html.contains(
+            "<blockquote id=\"__no-id-0\" class=\"quote \">\n\
+            <p>One</p>\n<p><span class=\" x\"> Two</span></p>\n</blockquote>\n"
+        ));
+
+        let html = render(&document(
+            ":refprefix:\n:quote: Quote\n\n:quote:[id=q] One\n::[attribution] _Hamlet_\n\n\
+            :quote:[id=r] Two\n\nSee :ref:[q] and :ref:[r].",
+        ));
+        assert!(html.contains(
+            "See <a class=\"reference \" href=\"#q\">Quote <i class=\" \">Hamlet</i></a> and \
+            <a class=\"reference \" href=\"#r\">Quote</a>."
+        ));
+    }
+
+    #[test]
+    fn footnote_contexts() {
+        let html = render(&document(
+            ":title: T:fn:{In title.}\n\n:toc:\n\n#[id=fn-1] Heading:fn:{Note.}\n\n\
+            See :ref:[fn-1] and :link:{x:fn:{y}}[http://a.b].",
+        ));
+        // the table of contents and cross references don't link to the footnote or repeat its ID
+        assert!(html.contains(
+            r##"<li><a href="#fn-1"> Heading<sup class="footnote-ref ">1</sup> </a></li>"##
+        ));
+        assert_eq!(html.matches(r#"id="fnref-1""#).count(), 1);
+        assert!(html.contains(
+            r##"Heading<sup class="footnote-ref "><a href="#fn-1-2" id="fnref-1">1</a></sup>"##
+        ));
+        // the ID of the heading is kept, and the footnote's ID is changed
+        assert_eq!(html.matches(r#"id="fn-1""#).count(), 1);
+        assert!(html.contains(r#"<li id="fn-1-2" value="1">Note. "#));
+        // links aren't nested, but the reference can still be linked back to
+        assert!(html.contains(
+            r##"<a class=" " href="http://a.b">x<sup class="footnote-ref "><span id="fnref-2">2</span></sup></a>"##
+        ));
+        // footnotes outside the text of blocks aren't numbered
+        assert!(html.contains(r#"T<span class="undefined-footnote">:fn:</span>"#));
+        assert!(!html.contains("fn-0"));
+
+        // an ID used by a footnote can't be used by a later block
+        let mut input = Input::new("A:fn:{B}\n\n#[id=fnref-1] C".as_bytes());
+        let mut document = Document::default();
+        document
+            .add_block(input.next_block().unwrap().parse().unwrap().unwrap())
+            .unwrap();
+        let block = input.next_block().unwrap().parse().unwrap().unwrap();
+        let err = document.add_block(block).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("fnref-1".into()));
+    }
+
+    #[test]
+    fn split_file_names() {
+        let mut document =
+            document("#[id=../x] Escape\n\n#[id=a/b] Slash\n\n#[id=a.b] Dot\n\n#[id=index] Index");
+        document.split_by_level(1);
+        let names = document
+            .files()
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "index.html",
+                "___x.html",
+                "a_b.html",
+                "a_b-2.html",
+                "index-2.html"
+            ]
+        );
+    }
+
+    #[test]
+    fn split_lang_scope() {
+        let french = temp_file(
+            "split-lang.txt",
+            ":lang: fr\n\nBonjour.\n\n# Suite\n\nEncore.",
+        );
+        let mut document = document(&format!("# Start\n\n:import: {}\n\n# End", french));
+        document.split_by_level(1);
+        let first = render_file(&document, 1);
+        assert_eq!(first.matches("<div lang").count(), 1);
+        assert_eq!(first.matches("</div>").count(), 1);
+        let second = render_file(&document, 2);
+        assert!(second.contains("Encore."));
+        assert!(!second.contains("</div>"));
+    }
+
+    #[test]
+    fn variants() {
+        let html = render(&document(
+            ":title: :var-of:{katu}{kado}\n\nEither :var-of:{katu}{*kado*}{kato}[free].",
+        ));
+        assert!(html.contains("<title> katu ~ kado </title>"));
+        assert!(html.contains(
+            "<span class=\"variants free\"><span class=\"variant\">katu</span> \
+            <span class=\"variant-separator\">~</span> <span class=\"variant\"><em class=\" \">kado</em></span> \
+            <span class=\"variant-separator\">~</span> <span class=\"variant\">kato</span></span>."
+        ));
+
+        let html = render(&document(":variantsep: /\n\n:var-of:{katu}{kado}"));
+        assert!(html.contains("<span class=\"variant-separator\">/</span>"));
+    }
+
+    #[test]
+    fn todos() {
+        let document = document(
+            "# One :todo:{rename}\n\nSome text.\n\n\
+            :gloss:\n::a\n::b :todo:{check *gloss*}[urgent]",
+        );
+        let html = render(&document);
+        assert!(html.contains(r#"<mark class="todo ">rename</mark>"#));
+        assert!(html.contains(r#"<mark class="todo urgent">check <em class=" ">gloss</em></mark>"#));
+        assert_eq!(
+            document.todos(),
+            [(0, "rename".into()), (4, "check gloss".into())]
+        );
+    }
+
+    #[test]
+    fn list_marker() {
+        let html = render(&document(
+            ":list:[id=a, marker=none]\n::1\n\n:list:[id=b, marker=—]\n::1\n  ::1a",
+        ));
+        assert!(html.contains(r#"<ul id="a" class="" style="list-style-type: none"><li>1</li>"#));
+        assert!(html
+            .contains(r#"<ul id="b" class="" style="list-style-type: &quot;— &quot;"><li>1<ul>"#));
+
+        let err = Input::new(r#":list:[marker="]"#.as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("marker".into(), "\"".into())
+        );
+    }
+
+    #[test]
+    fn flat_list() {
+        let html = render(&document(
+            ":list:[id=a, flat]\n::1\n\n:list:[id=b, plain, ordered, nav]\n::1\n\n:list:[id=c]\n::1",
+        ));
+        assert!(html.contains(r#"<ul id="a" class="flat"><li>1</li>"#));
+        assert!(html.contains(r#"<ol id="b" class="nav flat"><li>1</li>"#));
+        assert!(html.contains(r#"<ul id="c" class=""><li>1</li>"#));
+    }
+
+    #[test]
+    fn conlang_frequencies() {
+        let document = document(
+            ":replace:\n:name: katu\n\n\
+            # The `:name:` language\n\n\
+            Both `katu kado` and `katu`, but not *katu* or `sa`[gloss].\n\n\
+            :gloss:\n:: `kado`",
+        );
+        let frequencies = document.conlang_frequencies();
+        assert_eq!(frequencies.len(), 2);
+        assert_eq!(frequencies["katu"], 3);
+        assert_eq!(frequencies["kado"], 2);
+    }
+
+    #[test]
+    fn contents_root() {
+        let document = document(
+            ":toc:[root=sec-2] Contents\n\n\
+            # One\n\n## One A\n\n# Two\n\n## Two A\n\n### Two A i\n\n## Two B",
+        );
+        let html = render(&document);
+        let toc = &html[html.find("toc-heading").unwrap()..html.find("</nav>").unwrap()];
+        assert!(!toc.contains("One"));
+        assert!(!toc.contains(r##"href="#sec-2""##));
+        assert!(toc.contains(r##"<a href="#sec-2-1"> Two A </a>"##));
+        assert!(toc.contains(r##"<a href="#sec-2-1-1"> Two A i </a>"##));
+        assert!(toc.contains(r##"<a href="#sec-2-2"> Two B </a>"##));
+    }
+
+    #[test]
+    fn contents_numbers() {
+        let document = document(
+            ":toc:[numbers, anchors] Contents\n\n\
+            # One\n\n## One A\n\n#[id=appendix, nonumber] Appendix",
+        );
+        let html = render(&document);
+        let toc = &html[html.find("toc-heading").unwrap()..html.find("</nav>").unwrap()];
+        assert!(toc.contains(
+            r##"<a href="#sec-1"><span class="toc-number">1</span>  One </a> <span class="toc-anchor">#sec-1</span>"##
+        ));
+        assert!(
+            toc.contains(r##"<a href="#sec-1-1"><span class="toc-number">1.1</span>  One A </a>"##)
+        );
+        assert!(toc.contains(
+            r##"<a href="#appendix"> Appendix </a> <span class="toc-anchor">#appendix</span>"##
+        ));
+    }
+
+    #[test]
+    fn contents_title() {
+        let html = render(&document(
+            ":title: Grammar\n\n:toc:[title, data] Contents\n\n\
+            # One\n\n## One A",
+        ));
+        assert!(html.contains(r#"<h1 id="title" class="title"> Grammar </h1>"#));
+        let toc = &html[html.find("toc-heading").unwrap()..html.find("</nav>").unwrap()];
+        assert!(toc.contains(
+            "</p>\n<ol>\n\
+            <li class=\"nonumber\"><a href=\"#title\" data-section=\"title\"> Grammar </a><ol>\n\
+            <li><a href=\"#sec-1\" data-section=\"sec-1\"> One </a><ol>\n"
+        ));
+        assert!(toc.ends_with("</ol>\n\n</li>\n</ol>\n\n"));
+
+        let html = render(&document(":toc:[title]\n\n# One"));
+        assert!(!html.contains("#title"));
+
+        let html = render(&document(":title: Grammar\n\n# One"));
+        assert!(html.contains(r#"<h1 class="title"> Grammar </h1>"#));
+
+        let html = render(&document(
+            ":title: Grammar\n\n#[id=title] One\n\n:toc:[title] Contents",
+        ));
+        assert!(html.contains(r#"<h1 id="title-2" class="title"> Grammar </h1>"#));
+        assert!(html.contains(r#"<h1 id="title" class=" ">"#));
+        assert!(html.contains(r##"<a href="#title-2"> Grammar </a>"##));
+
+        let err = Document::from_reader(
+            ":title: Grammar\n\n:toc:[title] Contents\n\n#[id=title] One".as_bytes(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("title".into()));
+    }
+
+    #[test]
+    fn contents_root_not_heading() {
+        let document = document(":toc:[root=foo]\n\n:table:[id=foo]\n::|a");
+        let err = document.write(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.kind().to_string(),
+            ErrorKind::TocRoot("foo".into()).to_string()
+        );
+    }
+
+    #[test]
+    fn html_attributes() {
+        let document = document(":htmlattr:\n:class: dark\n:data-theme: sepia");
+        let html = render(&document);
+        assert!(html.starts_with("<!doctype html>\n<html class=\"dark\" data-theme=\"sepia\">\n"));
+
+        let err =
+            Document::from_reader(":htmlattr:\n:x onload=alert(1) y: z".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::AttributeName("x onload=alert(1) y".into())
+        );
+    }
+
+    #[test]
+    fn base_url() {
+        let html = render(&document(
+            ":baseurl: https://example.com/grammar\n\n:style: style.css\n\n\
+            :style: /root.css\n\n\
+            See :link:[other.html#sec-1], :link:[https://example.org/a], :link:[/b], and \
+            :link:[#sec-2].",
+        ));
+        assert!(html.contains(
+            r#"<link rel="stylesheet" type="text/css" href="https://example.com/grammar/style.css" />"#
+        ));
+        assert!(html.contains(r#"href="/root.css""#));
+        assert!(html.contains(r#"href="https://example.com/grammar/other.html#sec-1""#));
+        assert!(html.contains(r#"href="https://example.org/a""#));
+        assert!(html.contains(r#"href="/b""#));
+        assert!(html.contains(r##"href="#sec-2""##));
+    }
+
+    #[test]
+    fn groups() {
+        let html = render(&document(
+            ":group:[aside, id=outer, lang=ar, dir=rtl]\n\nOne.\n\n\
+            :group:[id=inner]\n\nTwo.\n\n:endgroup:\n\nThree.\n\n:endgroup:\n\nFour.\n\n\
+            :group:[id=open]\n\nFive.",
+        ));
+        assert!(html.contains(
+            "<div id=\"outer\" class=\"group aside\" lang=\"ar\" dir=\"rtl\">\n<p>One. </p>\n\n\
+            <div id=\"inner\" class=\"group \">\n<p>Two. </p>\n\n</div>\n\n\
+            <p>Three. </p>\n\n</div>\n\n<p>Four. </p>\n\n\
+            <div id=\"open\" class=\"group \">\n<p>Five. </p>\n\n</div>\n\n</body>"
+        ));
+
+        let mut input = Input::new("Text.\n\n:endgroup:".as_bytes());
+        let mut document = Document::default();
+        document
+            .add_block(input.next_block().unwrap().parse().unwrap().unwrap())
+            .unwrap();
+        let block = input.next_block().unwrap().parse().unwrap().unwrap();
+        let err = document.add_block(block).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::EndGroup(2));
+
+        let err = Document::from_reader(":columns:\n\nText.\n\n:endgroup:".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::EndGroup(4));
+        let err = Document::from_reader(":group:\n\nText.\n\n:endcolumns:".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::EndColumns(4));
+    }
+
+    #[test]
+    fn max_heading() {
+        let html = render(&document(":maxheading: 2\n\n# One\n\n## Two\n\n### Three"));
+        assert!(html.contains("<h2 id=\"sec-1-1\" class=\" \"><span class=\"secnum\">"));
+        assert!(html.contains(
+            "<p id=\"sec-1-1-1\" class=\" collapsed-heading h3\"><b><span class=\"secnum\">\
+            <span class=\"secnum\"><span class=\"secnum\">1.</span>1.</span>1.</span> Three </b></p>"
+        ));
+
+        let html = render(&document(":maxheading: 1 nonumber\n\n# One\n\n## Two"));
+        assert!(html.contains("<p id=\"sec-1-1\" class=\" collapsed-heading h2\"><b> Two </b></p>"));
+
+        let err = Input::new(":maxheading: 1 flat".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("maxheading".into(), "1 flat".into())
+        );
+    }
+
+    #[test]
+    fn generator() {
+        let html = render(&document("Text."));
+        assert!(html.starts_with(
+            "<!doctype html>\n<html>\n<head>\n<meta charset=\"utf-8\" />\n\
+            <meta name=\"generator\" content=\"conlang_fmt\" />\n</head>\n"
+        ));
+        let mut omitted = document("Text.");
+        omitted.set_omit_generator(true);
+        assert!(!render(&omitted).contains("generator"));
+    }
+
+    #[test]
+    fn no_default_classes() {
+        let text = "#[id=a, class=intro] Heading\n\nSee :ref:[a].";
+        let html = render(&document(text));
+        assert!(html.contains(r#"<h1 id="a" class="intro "><span class="secnum">1.</span>"#));
+        assert!(html.contains(r##"<a class="reference " href="#a">"##));
+
+        let mut plain = document(text);
+        plain.set_omit_default_classes(true);
+        let html = render(&plain);
+        assert!(!html.contains("secnum"));
+        assert!(html.contains(r#"<h1 id="a" class="intro"><span>1.</span>"#));
+        assert!(html.contains(r##"<a href="#a">"##));
+        assert!(!html.contains("class=\"\"") && !html.contains("class=\" "));
+
+        let text = ":gloss:[compact] Title\n::a b\n\n\
+            :table:[caption=bottom] Nouns\n::|a";
+        let html = render(&document(text));
+        assert!(html.contains(r#"class="gloss compact ""#));
+        assert!(html.contains(r#"<caption class="caption-bottom" "#));
+
+        let mut plain = document(text);
+        plain.set_omit_default_classes(true);
+        let html = render(&plain);
+        assert!(html.contains(r#"<p id="__no-id-0" role="figure" "#));
+        assert!(html.contains(r#"<caption style="caption-side: bottom">"#));
+        assert!(!html.contains("class=\"\"") && !html.contains("class=\" "));
+    }
+
+    #[test]
+    fn list_item_reference() {
+        let html = render(&document(
+            ":list:[ordered]\n::First\n::[id=second] Second\n  ::[id=nested] Nested\n\n\
+            :list:\n::[id=bullet] Bullet\n\n\
+            See :ref:[second], :ref:[nested], :numref:[second], and :ref:[bullet].",
+        ));
+        assert!(html.contains(r#"<li id="second">Second<ol>"#));
+        assert!(html.contains(
+            r##"See <a class="reference " href="#second">item 2</a>, <a class="reference " href="#nested">item 2.1</a>, <a class="reference " href="#second">2</a>, and <a class="reference " href="#bullet">item Bullet</a>."##
+        ));
+
+        let mut input = Input::new(":list:\n::[id=a] A\n\n#[id=a] Heading".as_bytes());
+        let mut document = Document::default();
+        document
+            .add_block(input.next_block().unwrap().parse().unwrap().unwrap())
+            .unwrap();
+        let block = input.next_block().unwrap().parse().unwrap().unwrap();
+        let err = document.add_block(block).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("a".into()));
+    }
+
+    #[test]
+    fn render_block() {
+        let mut input = Input::new(
+            ":table:[id=t]\n::|a\n\nSee :ref:[t] and :ref:[g].\n\n\
+            :gloss:[id=g]\n::a"
+                .as_bytes(),
+        );
+        let mut document = Document::default();
+        for _ in 0..2 {
+            let block = input.next_block().unwrap().parse().unwrap().unwrap();
+            document.add_block(block).unwrap();
+        }
+        assert_eq!(
+            document.render_block("__no-id-0").unwrap().unwrap(),
+            "<p>See <a class=\"reference \" href=\"#t\">table 1</a> and \
+            <a class=\"reference \" href=\"#g\"><span class=\"undefined-reference\">#g</span></a>. </p>\n\n"
+        );
+        assert!(document.render_block("g").is_none());
+        assert!(document
+            .render_block("t")
+            .unwrap()
+            .unwrap()
+            .starts_with("<table id=\"t\""));
+    }
+
+    #[test]
+    fn columns() {
+        let html = render(&document(
+            ":columns:[id=words] 2\n\n:list:\n::a\n::b\n::c\n\n:endcolumns:\n\n\
+            :columns:[id=default, lang=fr]\n\nText.",
+        ));
+        assert!(html.contains(
+            "<div id=\"words\" class=\"columns \" style=\"column-count:2\">\n\
+            <ul id=\"__no-id-0\" class=\"\"><li>a</li>\n<li>b</li>\n<li>c</li>\n</ul>\n\
+            </div>\n\n"
+        ));
+        assert!(html.contains(
+            "<div id=\"default\" class=\"columns \" lang=\"fr\" style=\"column-count:2\">\n\
+            <p>Text. </p>\n\n</div>"
+        ));
+
+        let err = Input::new(":columns: 0".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("columns".into(), "0".into())
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        let text = "*a* **b** _c_[x] __d__";
+        let html = render(&document(text));
+        assert!(html.contains(
+            r#"<p><em class=" ">a</em> <strong class=" ">b</strong> <i class=" x">c</i> <b class=" ">d</b> </p>"#
+        ));
+
+        let html = render(&document(&format!(":formatting: classes\n\n{}", text)));
+        assert!(html.contains(
+            r#"<p><span class="emphasis ">a</span> <span class="strong ">b</span> <span class="italics x">c</span> <span class="bold ">d</span> </p>"#
+        ));
+
+        let err = Input::new(":formatting: css".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("formatting".into(), "css".into())
+        );
+    }
+
+    #[test]
+    fn doctype() {
+        let html = render(&document(":doctype: none"));
+        assert!(html.starts_with("<html>\n"));
+        let html = render(&document(":doctype: HTML"));
+        assert!(html.starts_with("<!doctype HTML>\n<html>\n"));
+    }
+
+    #[test]
+    fn explicit_numbers() {
+        let document = document(
+            ":table:[id=a]\n::|a\n\n\
+            :table:[id=b, number=3]\n::|b\n\n\
+            :table:[id=c]\n::|c\n\n\
+            :table:[id=d]\n::|d\n\n\
+            :table:[id=e]\n::|e\n\n\
+            See :ref:[a], :ref:[b], :ref:[c], :ref:[d], and :ref:[e].",
+        );
+        let html = render(&document);
+        assert!(html.contains(r##"<a class="reference " href="#a">table 1</a>"##));
+        assert!(html.contains(r##"<a class="reference " href="#b">table 3</a>"##));
+        assert!(html.contains(r##"<a class="reference " href="#c">table 2</a>"##));
+        assert!(html.contains(r##"<a class="reference " href="#d">table 4</a>"##));
+        assert!(html.contains(r##"<a class="reference " href="#e">table 5</a>"##));
+    }
+
+    #[test]
+    fn duplicate_explicit_numbers() {
+        let mut document = document(":gloss:\n:: a\n\n:gloss:[number=4]\n:: b");
+        let mut input = Input::new(":gloss:[number=1]\n:: c".as_bytes());
+        let block = input.next_block().unwrap().parse().unwrap().unwrap();
+        let err = document.add_block(block).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Number("gloss", 1));
+
+        for source in [":table:[number=0]\n::|a", ":gloss:[number=0]\n:: a"] {
+            let err = Input::new(source.as_bytes())
+                .next_block()
+                .unwrap()
+                .parse()
+                .unwrap_err();
+            assert_eq!(
+                err.kind(),
+                &ErrorKind::ParameterValue("number".into(), "0".into())
+            );
+        }
+    }
+
+    fn render_file(document: &Document, file: usize) -> String {
+        let mut out = Vec::new();
+        document.write_file(&mut out, file).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn split_by_level() {
+        let mut document = document(
+            ":title: Grammar\n\n:toc:\n\n\
+            # Phonology\n\nSee :ref:[nouns] and :ref:[vowels].\n\n\
+            :table:[id=vowels] Vowels\n::|a\n\n\
+            # Morphology\n\n##[id=nouns] Nouns\n\nBack to :ref:[vowels].",
+        );
+        document.split_by_level(1);
+        let names = document
+            .files()
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["index.html", "sec-1.html", "sec-2.html"]);
+
+        let index = render_file(&document, 0);
+        assert!(index.contains(r##"<a href="sec-1.html#sec-1"> Phonology </a>"##));
+        assert!(index.contains(r##"<a href="sec-2.html#nouns"> Nouns </a>"##));
+        assert!(index.contains(r#"<li><a href="sec-1.html"> Phonology </a></li>"#));
+        assert!(index.contains(r#"<li><a href="sec-2.html"> Morphology </a></li>"#));
+        assert!(!index.contains("Vowels"));
+
+        let first = render_file(&document, 1);
+        assert!(first.contains(r##"href="sec-2.html#nouns">section "##));
+        assert!(first.contains(r##"href="#vowels">table 1</a>"##));
+        assert!(!first.contains("Morphology"));
+
+        let second = render_file(&document, 2);
+        assert!(second.contains(r##"href="sec-1.html#vowels">table 1</a>"##));
+        assert!(!second.contains("Phonology"));
+    }
+
+    #[test]
+    fn split_references() {
+        let mut document = document(
+            "# Words\n\n:list:[ordered]\n::[id=first] First\n\n\
+            See :cite:[smith] and :ref:[first].\n\n\
+            # Sources\n\n:bibliography:\n::[smith, author=Smith, year=2001] Grammar\n\n\
+            Cited in :ref:[first], see :cite:[smith].",
+        );
+        document.split_by_level(1);
+
+        let words = render_file(&document, 1);
+        assert!(words.contains(r##"href="sec-2.html#smith">Smith 2001</a>"##));
+        assert!(words.contains(r##"href="#first">item 1</a>"##));
+
+        let sources = render_file(&document, 2);
+        assert!(sources.contains(r##"href="sec-1.html#first">item 1</a>"##));
+        assert!(sources.contains(r##"href="#smith">Smith 2001</a>"##));
+    }
+
+    #[test]
+    fn bidi_controls() {
+        let html = render(&document("abc\u{202E}def *g\u{202D}h*"));
+        assert!(html.contains("<p>abc&#x202E;def <em class=\" \">g&#x202D;h</em> </p>"));
+
+        let mut document = Document::default();
+        document.set_bidi_controls(BidiControls::Keep);
+        document.read("abc\u{202E}def".as_bytes(), None).unwrap();
+        assert!(render(&document).contains("<p>abc\u{202E}def </p>"));
+        assert_eq!(
+            document.input_warnings(),
+            [ErrorKind::BidiControl(0x202E, 0)]
+        );
+    }
+
+    #[test]
+    fn span_audio() {
+        let html = render(&document(
+            "Say `ranna`[audio=ranna.ogg] and `tel`[audio=tel.ogg, class=word].",
+        ));
+        assert!(html.contains(
+            r#"<a class="audio" href="ranna.ogg" data-audio="ranna.ogg"><span class=" conlang">ranna</span></a>"#
+        ));
+        assert!(html.contains(
+            r#"<a class="audio" href="tel.ogg" data-audio="tel.ogg"><span class=" word">tel</span></a>"#
+        ));
+    }
+
+    #[test]
+    fn table_wrapping() {
+        let html = render(&document(
+            ":table:[id=plain] Plain\n::|a|b\n\n\
+            :table:[id=wide, scroll] Wide\n||[nowrap]\n::|[wrap]a|b",
+        ));
+        assert_eq!(html.matches(r#"<div class="table-scroll""#).count(), 1);
+        assert!(
+            html.contains(r#"<div class="table-scroll" style="overflow-x:auto"><table id="wide" "#)
+        );
+        assert_eq!(html.matches("</table></div>").count(), 1);
+        assert!(html.contains(r#"<td class="  wrap">a</td><td class="  nowrap">b</td>"#));
+        assert!(html.contains(r#"<td class="">a</td><td class="">b</td>"#));
+    }
+
+    #[test]
+    fn text_run() {
+        let html = render(&document("Before.\n\n:text: Some *raw* text.\n\nAfter."));
+        assert!(html
+            .contains("<p>Before. </p>\n\nSome <em class=\" \">raw</em> text. \n<p>After. </p>"));
+    }
+
+    #[test]
+    fn number_styles() {
+        let html = render(&document(
+            ":numberstyle:\n:table: upper-roman\n:gloss: lower-alpha\n\n\
+            :table:[id=t, number=4] Verbs\n::|a\n\n\
+            :gloss:[id=g, number=28]\n::a\n::b\n\n\
+            See :ref:[t], :numref:[t], and :ref:[g].",
+        ));
+        assert!(html.contains(r#"<span class="table-heading-prefix">Table IV:</span>"#));
+        assert!(html.contains(r#"<span class="gloss-heading-prefix">Gloss ab:</span>"#));
+        assert!(html.contains(
+            r##"See <a class="reference " href="#t">table IV</a>, <a class="reference " href="#t">IV</a>, and <a class="reference " href="#g">gloss ab</a>."##
+        ));
+
+        let err = Input::new(":numberstyle:\n:table: greek".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("table".into(), "greek".into())
+        );
+
+        let err = Input::new(":numberstyle:\n:tabel: upper-roman".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Parameter("tabel".into()));
+    }
+
+    #[test]
+    fn id_prefixes() {
+        let html = render(&document(
+            ":idprefix:\n:section: s-\n:noid: anchor-\n\n# One\n\n## Two\n\n:table: T\n::|a",
+        ));
+        assert!(html.contains(r#"<h1 id="s-1" "#));
+        assert!(html.contains(r#"<h2 id="s-1-1" "#));
+        assert!(html.contains(r#"<table id="anchor-1" "#));
+        assert!(!html.contains("sec-"));
+        assert!(!html.contains("__no-id-"));
+    }
+
+    #[test]
+    fn aria_labels() {
+        let html = render(&document(
+            ":toc:\n\n# A\n\n:gloss:\n::a\n::b\n\n:gloss:[nonumber]\n::a\n::b",
+        ));
+        assert!(html.contains(r#"class=" toc" aria-label="Table of contents">"#));
+        assert!(html.contains("</ol>\n\n</nav>"));
+        assert!(html.contains(r#"role="figure" aria-label="Gloss 1">"#));
+        assert!(html.contains(r#"role="figure" aria-label="Gloss">"#));
+
+        let html = render(&document(
+            ":arialabel:\n:toc: Inhalt\n:gloss: Beispiel\n\n:toc:\n\n# A\n\n:gloss:\n::a\n::b",
+        ));
+        assert!(html.contains(r#"class=" toc" aria-label="Inhalt">"#));
+        assert!(html.contains(r#"role="figure" aria-label="Beispiel 1">"#));
+    }
+
+    /// Writes `contents` to a file in the temporary directory, returning its path.
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("conlang-fmt-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().into()
+    }
+
+    #[test]
+    fn safe_mode() {
+        let path = temp_file("safe-mode.txt", "Imported.");
+        let source = format!(":import: {}\n\nText.", path);
+        let mut document = Document::default();
+        document.set_safe(true);
+        let err = document.read(source.as_bytes(), None).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::FileAccess(path));
+
+        let mut document = Document::from_reader(":style: style.css".as_bytes()).unwrap();
+        document.set_safe(true);
+        let err = document.copy_assets(&std::env::temp_dir()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::FileAccess("style.css".into()));
+
+        let mut document = Document::default();
+        document.set_safe(true);
+        let err = document
+            .read(":defaultblock: raw\n\n<script></script>".as_bytes(), None)
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::UnsafeHtml("`:defaultblock: raw`".into())
+        );
+
+        let mut document = Document::default();
+        document.set_safe(true);
+        let err = document
+            .read(":htmlattr:\n:onLoad: alert(1)".as_bytes(), None)
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::UnsafeHtml("attribute `onLoad`".into())
+        );
+    }
+
+    #[test]
+    fn lenient_imports() {
+        let source = "Before.\n\n:import: missing-file.txt\n\nAfter.";
+        let err = Document::from_reader(source.as_bytes()).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::FileNotFound(_)));
+
+        let mut document = Document::default();
+        document.set_lenient_imports(true);
+        document.read(source.as_bytes(), None).unwrap();
+        assert_eq!(
+            document.input_warnings(),
+            [ErrorKind::FileNotFound("missing-file.txt".into())]
+        );
+        assert!(render(&document).contains(
+            "<p>Before. </p>\n\n\
+            <div class=\"missing-import\">Missing import: <code>missing-file.txt</code></div>\n\n\
+            <p>After. </p>"
+        ));
+    }
+
+    #[test]
+    fn copy_assets() {
+        // paths starting with `/` are relative to the root of the site, so the files are given
+        // relative paths, from the package directory the tests are run in
+        let root = Path::new("target").join(format!("conlang-fmt-{}-assets", std::process::id()));
+        let (a, b, out) = (root.join("a"), root.join("b"), root.join("out"));
+        for dir in [&a, &b, &out] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(a.join("style.css"), "a").unwrap();
+        std::fs::write(b.join("style.css"), "b").unwrap();
+        std::fs::write(a.join("word.ogg"), "ogg").unwrap();
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+        let mut document = document(&format!(
+            ":style: {a}/style.css\n\n:style: {b}/style.css\n\n\
+            :style: https\\://example.com/style.css\n\n:style: /site.css\n\n\
+            Say `ranna`[audio={a}/word.ogg] and `tel`[audio={a}/word.ogg].\n\n\
+            See :link:{{the style}}[{a}/style.css]."
+        ));
+        document.copy_assets(&out).unwrap();
+        assert_eq!(std::fs::read_to_string(out.join("style.css")).unwrap(), "a");
+        assert_eq!(
+            std::fs::read_to_string(out.join("style-1.css")).unwrap(),
+            "b"
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("word.ogg")).unwrap(),
+            "ogg"
+        );
+
+        let html = render(&document);
+        assert!(html.contains(r#"href="style.css" />"#));
+        assert!(html.contains(r#"href="style-1.css" />"#));
+        assert!(html.contains(r#"href="https://example.com/style.css" />"#));
+        assert!(html.contains(r#"href="/site.css" />"#));
+        assert!(html.contains(&format!(r#"href="{a}/style.css">the style</a>"#)));
+        assert_eq!(
+            html.matches(r#"href="word.ogg" data-audio="word.ogg""#)
+                .count(),
+            2
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn import_lang() {
+        let french = temp_file("french.txt", ":lang: fr\n\nBonjour.");
+        let plain = temp_file("plain.txt", "Hello again.");
+        let document = document(&format!(
+            ":lang: en\n\nHello.\n\n:import: {}\n\n:import: {}",
+            french, plain
+        ));
+        let html = render(&document);
+        assert!(html.contains("<div lang=\" fr \">\n<p>Bonjour. </p>\n\n</div>"));
+        assert!(html.contains("</div>\n\n<p>Hello again. </p>"));
+        assert_eq!(html.matches("<div lang").count(), 1);
+        assert!(html.contains("<html lang=\" en \">"));
+        // the `lang` of the imported file doesn't use up any automatic IDs
+        assert_eq!(
+            document.render_block("__no-id-2").unwrap().unwrap(),
+            "<p>Bonjour. </p>\n\n"
+        );
+    }
+
+    #[test]
+    fn timestamp_footer() {
+        let mut document = document("Some text.");
+        assert!(!render(&document).contains("<footer"));
+        document.set_timestamp(1_700_000_000);
+        let html = render(&document);
+        assert!(html.contains(
+            "<p>Some text. </p>\n\n<footer class=\"timestamp\">Last updated \
+            <time datetime=\"2023-11-14T22:13:20Z\">2023-11-14</time></footer>\n</body>"
+        ));
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(11016), (2000, 2, 29));
+        assert_eq!(civil_date(11017), (2000, 3, 1));
+    }
+
+    #[test]
+    fn table_banner() {
+        let html = render(&document(
+            ":table:[banner] Verbs\n::[header]|[cols=2]sg|pl\n::|[rows=2]a|b|c\n::|d|e",
+        ));
+        assert!(!html.contains("<caption>"));
+        assert!(html.contains(
+            "<tr class=\"banner\"><th colspan=\"3\" scope=\"colgroup\">\
+            <span class=\"table-heading-prefix\">Table 1:</span>  Verbs</th></tr>\n\
+            <tr class=\"\"><th scope=\"colgroup\" colspan=\"2\" "
+        ));
+    }
+
+    #[test]
+    fn table_plain() {
+        let html = render(&document(
+            ":table:[id=layout, plain, banner] Layout\n::|a|b\n\n\
+            :table:[id=verbs] Verbs\n::|c\n\nSee :ref:[layout] and :ref:[verbs].",
+        ));
+        assert!(html.contains("<table id=\"layout\" class=\"\"><tr class=\"\">"));
+        assert!(!html.contains("Layout</caption>"));
+        assert!(!html.contains("Layout</th>"));
+        assert!(html.contains(
+            "<caption><span class=\"table-heading-prefix\">Table 1:</span>  Verbs</caption>"
+        ));
+        assert!(html.contains(r##"<a class="reference " href="#layout">table  Layout</a>"##));
+        assert!(html.contains(r##"<a class="reference " href="#verbs">table 1</a>"##));
+    }
+
+    #[test]
+    fn table_caption_bottom() {
+        let html = render(&document(
+            ":table:[caption=bottom] Nouns\n::|a\n\n:table:[caption=top] Verbs\n::|b",
+        ));
+        assert!(html.contains(
+            "<caption class=\"caption-bottom\" style=\"caption-side: bottom\">\
+            <span class=\"table-heading-prefix\">Table 1:</span>  Nouns</caption>"
+        ));
+        assert!(html.contains(
+            "<caption><span class=\"table-heading-prefix\">Table 2:</span>  Verbs</caption>"
+        ));
+    }
+
+    #[test]
+    fn import_error_source() {
+        let bad = temp_file("bad.txt", ":table:\n::|[rows=x]a");
+        let source = format!(":import: {}", bad);
+        let mut input = Input::new(source.as_bytes());
+        let block = input.next_block().unwrap().parse().unwrap().unwrap();
+        let err = Document::default().add_block(block).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Parse);
+        assert_eq!(err.location().source, Some(bad));
+
+        let duplicate = temp_file("duplicate.txt", ":lang: fr\n\n#[id=a] Deux");
+        let source = format!("#[id=a] One\n\n:import: {}", duplicate);
+        let err = Document::from_reader(source.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Id("a".into()));
+        assert_eq!(err.location().source, Some(duplicate));
+    }
+
+    #[test]
+    fn shared_numbers() {
+        let html = render(&document(
+            ":sharednumbers:\n\n:table:[id=t1] A\n::|a\n\n:gloss:[id=g2]\n::a\n\n\
+            :table:[id=t3] B\n::|b\n\n:gloss:[id=g4]\n::b\n\n\
+            See :ref:[t1], :ref:[g2], :ref:[t3], and :ref:[g4].",
+        ));
+        assert!(html.contains("Table 1:"));
+        assert!(html.contains(r#"aria-label="Gloss 2""#));
+        assert!(html.contains("Table 3:"));
+        assert!(html.contains(r#"aria-label="Gloss 4""#));
+        assert!(html.contains(
+            r##"See <a class="reference " href="#t1">table 1</a>, <a class="reference " href="#g2">gloss 2</a>, <a class="reference " href="#t3">table 3</a>, and <a class="reference " href="#g4">gloss 4</a>."##
+        ));
+
+        let err = Document::from_reader(
+            ":sharednumbers:\n\n:table: A\n::|a\n\n:gloss:[number=1]\n::a".as_bytes(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Number("gloss", 1));
+    }
+
+    #[test]
+    fn continue_numbering() {
+        let first = document(
+            "# One\n\n:table: A\n::|a\n\n:gloss:\n::a\n\n# Two\n\n:table:[number=5] B\n::|b",
+        );
+        let numbering = first.numbering();
+        assert_eq!(
+            numbering,
+            Numbering {
+                sections: 2,
+                tables: 5,
+                glosses: 1,
+            }
+        );
+        assert_eq!(numbering.to_string().parse(), Ok(numbering));
+        assert_eq!(
+            "table x".parse::<Numbering>(),
+            Err(ErrorKind::NumberingLine("table x".into()))
+        );
+
+        let text = "# Three\n\n:table: C\n::|c\n\n:gloss:\n::a";
+        let mut second = Document::default();
+        second.set_numbering(numbering);
+        second.read(text.as_bytes(), None).unwrap();
+        let html = render(&second);
+        assert!(html.contains(r#"<span class="secnum">3.</span>"#));
+        assert!(html.contains("Table 6:"));
+        assert!(html.contains(r#"aria-label="Gloss 2""#));
+
+        let mut third = Document::default();
+        third.set_start_section_number(10);
+        third.set_start_table_number(20);
+        third.set_start_gloss_number(30);
+        third.read(text.as_bytes(), None).unwrap();
+        let html = render(&third);
+        assert!(html.contains(r#"<span class="secnum">10.</span>"#));
+        assert!(html.contains("Table 20:"));
+        assert!(html.contains(r#"aria-label="Gloss 30""#));
+    }
+
+    #[test]
+    fn post_processors() {
+        let mut document = document("# Heading\n\nSome marker text.");
+        document.add_post_processor(|html| html.replace("marker", "MARKER"));
+        document.add_post_processor(|html| html.replace("MARKER", "<b>MARKER</b>"));
+        let html = render(&document);
+        assert!(html.contains("<p>Some <b>MARKER</b> text. </p>"));
+        assert!(!html.contains("marker"));
+
+        document.split_by_level(1);
+        let mut out = Vec::new();
+        document.write_file(&mut out, 1).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("<b>MARKER</b>"));
+    }
+
+    #[test]
+    fn typed_error() {
+        let err = Document::from_reader(":table: Title\n::|a|b\n|c|d".as_bytes()).unwrap_err();
+        match err.kind() {
+            ErrorKind::LineMarker(line) => assert_eq!(*line, 2),
+            kind => panic!("unexpected error kind {kind:?}"),
+        }
+        assert_eq!(
+            err.location(),
+            &Location {
+                source: None,
+                line: Some(0),
+            }
+        );
+        assert_eq!(err.to_string(), "Failed to parse block starting on line 0");
+
+        let err = document(":undefined: error\n\nSee :ref:[foo].")
+            .write(&mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::UndefinedReference(id) if id == "foo"));
+        assert_eq!(err.location().line, Some(2));
+    }
+
+    #[test]
+    fn transcription() {
+        let html = render(&document(":title: :phon:a:\n\n:phonemic:a:"));
+        assert!(html.contains("<title> [a] </title>"));
+        assert!(html.contains(r#"<p><span class=" ipa phonemic">/a/</span> </p>"#));
+    }
+
+    #[test]
+    fn gloss_example_style() {
+        let html = render(&document(
+            ":gloss:[style=example] Title\n::a\n::b\n\n:gloss:[style=example, nonumber]\n::a\n::b",
+        ));
+        assert!(html.contains(
+            r#"class="gloss example " role="figure" aria-label="Gloss 1"><p class="gloss-heading"><span class="gloss-number">(1)</span>  Title"#
+        ));
+        assert!(html.contains(r#"aria-label="Gloss"><p class="gloss-heading"></p>"#));
+        assert!(!html.contains("gloss-heading-prefix"));
+
+        let err = Input::new(":gloss:[style=fancy]\n::a".as_bytes())
+            .next_block()
+            .unwrap()
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ParameterValue("style".into(), "fancy".into())
+        );
+    }
+
+    #[test]
+    fn gloss_compact() {
+        let source = "::kitab -lar\n::book -PL";
+        let full = render(&document(&format!(":gloss:[id=g]\n{}", source)));
+        let compact = render(&document(&format!(":gloss:[id=g, compact]\n{}", source)));
+        assert!(full.contains(r#"<div id="g" class="gloss " role="figure" aria-label="Gloss 1">"#));
+        assert!(full.contains(r#"<dl><dt class="">kitab</dt><dd class="">book</dd></dl>"#));
+        assert!(compact.contains(
+            r#"<p id="g" class="gloss compact " role="figure" aria-label="Gloss 1"><span class="gloss-heading-prefix">Gloss 1:</span> <span class="">kitab-lar</span> (<span class="">book-PL</span>)</p>"#
+        ));
+        assert!(!compact.contains("<dl>"));
+    }
+
+    #[test]
+    fn gloss_single_line() {
+        let html = render(&document(
+            ":gloss:[id=g] Draft\n::[source] kitab -lar\n\nSee :ref:[g].",
+        ));
+        assert!(html.contains(
+            "<div id=\"g\" class=\"gloss \" role=\"figure\" aria-label=\"Gloss 1\">\
+            <p class=\"gloss-heading\"><span class=\"gloss-heading-prefix\">Gloss 1:</span>  Draft</p>\n\
+            <p class=\"source\">kitab-lar</p>\n</div>"
+        ));
+        assert!(!html.contains("<dl>"));
+        assert!(html.contains(r##"<a class="reference " href="#g">gloss 1</a>"##));
+    }
+
+    #[test]
+    fn gloss_tooltip() {
+        let html = render(&document(
+            ":gloss:\n::kitab -lar iyi\n::[tooltip] {Arabic loan} plural\n\
+            ::book -PL good\n::[tooltip] _noun_",
+        ));
+        assert!(html.contains(
+            "<dl><dt class=\"\" title=\"Arabic loan\nnoun\">kitab</dt><dd class=\"\">book</dd></dl>\
+            <dl><dt class=\"\" title=\"plural\">-lar</dt><dd class=\"\">-PL</dd></dl> \
+            <dl><dt class=\"\">iyi</dt><dd class=\"\">good</dd></dl>"
+        ));
+        assert!(!html.contains("<dd class=\"\">plural"));
+
+        let html = render(&document(":gloss:\n::kitab\n::[tooltip] book"));
+        assert!(html.contains("<dl><dt class=\"\" title=\"book\">kitab</dt></dl>"));
+    }
+
+    #[test]
+    fn gloss_tiers() {
+        let html = render(&document(
+            ":gloss:\n::[tier=translation] 'the books'\n::[tier=gloss] the book-PL\n\
+            ::[tier=orthography] la libroj\n::[tier=ipa] la libroj\n::[nosplit] (Esperanto)",
+        ));
+        assert!(html.contains(
+            "<dl><dt class=\"tier-orthography \">la</dt><dd class=\"tier-ipa \">la</dd>\
+            <dd class=\"tier-gloss \">the</dd></dl>"
+        ));
+        assert!(html.contains(
+            "</dl><p class=\"translation\"> &#x27;the books&#x27;</p>\n\
+            <p class=\"postamble\"> (Esperanto)</p>"
+        ));
+
+        let html = render(&document(
+            ":gloss:[compact]\n::[tier=gloss] book\n::[tier=translation] 'a book'\n\
+            ::[tier=orthography] libro",
+        ));
+        assert!(html.contains(
+            "<span class=\"tier-orthography \">libro</span> \
+            (<span class=\"tier-gloss \">book</span>) \
+            <span class=\"translation\"> &#x27;a book&#x27;</span>"
+        ));
+    }
+
+    #[test]
+    fn gloss_attribution() {
+        let html = render(&document(
+            ":gloss:\n::[attribution] _Corpus_, p. 12\n::kitab-lar\n::book-PL\n::[nosplit] (Turkish)",
+        ));
+        assert!(html.contains(
+            "<p class=\"postamble\"> (Turkish)</p>\n\
+            <p><cite class=\"gloss-source\"> <i class=\" \">Corpus</i>, p. 12</cite></p>\n</div>"
+        ));
+
+        let html = render(&document(
+            ":gloss:[compact]\n::kitab\n::book\n::[attribution, class=x] speaker A",
+        ));
+        assert!(html.contains(
+            "(<span class=\"\">book</span>) \
+            <cite class=\"gloss-source\"><span class=\" x\"> speaker A</span></cite></p>"
+        ));
+    }
+
+    #[test]
+    fn gloss_empty_cell() {
+        let html = render(&document(":gloss:[emptycell=—]\n::kitab iyi\n::book"));
+        assert!(html.contains(
+            "<dl><dt class=\"\">kitab</dt><dd class=\"\">book</dd></dl> \
+            <dl><dt class=\"\">iyi</dt><dd class=\"\">—</dd></dl>"
+        ));
+
+        let html = render(&document(":gloss:\n::kitab\n::book good"));
+        assert!(html.contains("<dl><dt class=\"\"></dt><dd class=\"\">good</dd></dl>"));
+    }
+
+    #[test]
+    fn gloss_highlight() {
+        let html = render(&document(
+            ":gloss:[highlight=2]\n::kitab -lar iyi\n::book -PL good",
+        ));
+        assert!(html.contains(
+            "<dl><dt class=\"\">kitab</dt><dd class=\"\">book</dd></dl>\
+            <dl><dt class=\"highlight \">-lar</dt><dd class=\"highlight \">-PL</dd></dl> \
+            <dl><dt class=\"\">iyi</dt><dd class=\"\">good</dd></dl>"
+        ));
+
+        let html = render(&document(
+            ":gloss:[compact, highlight=1]\n::kitab -lar\n::book -PL",
+        ));
+        assert!(html.contains(
+            "<span class=\"\"><span class=\"highlight\">kitab</span>-lar</span> \
+            (<span class=\"\"><span class=\"highlight\">book</span>-PL</span>)"
+        ));
+
+        for column in ["0", "4"] {
+            let text = format!(":gloss:[highlight={column}]\n::kitab -lar iyi\n::book -PL good");
+            let err = Document::from_reader(text.as_bytes()).unwrap_err();
+            assert_eq!(
+                err.kind(),
+                &ErrorKind::HighlightColumn(column.parse().unwrap(), 3)
+            );
+        }
+    }
+
+    #[test]
+    fn gloss_gap() {
+        let html = render(&document(
+            ":gloss:[gap=gloss-gap]\n::kitab -lar iyi\n::book -PL good\n\n\
+            :gloss:[gap=gloss-gap]\n::kitab -lar iyi",
+        ));
+        assert!(html.contains(
+            "<span class=\"gloss-gap\"> </span><dl><dt class=\"\">kitab</dt><dd class=\"\">book</dd></dl>\
+            <dl><dt class=\"\">-lar</dt><dd class=\"\">-PL</dd></dl>\
+            <span class=\"gloss-gap\"> </span><dl><dt class=\"\">iyi</dt>"
+        ));
+        assert!(html.contains("<p class=\"\">kitab-lar<span class=\"gloss-gap\"> </span>iyi</p>"));
+    }
+
+    #[test]
+    fn contents_active_section() {
+        let mut active = document(":toc:[data]\n\n# One\n\n# Two");
+        active.set_active_section("sec-2".into()).unwrap();
+        let html = render(&active);
+        assert!(html.contains(r##"<a href="#sec-1" data-section="sec-1"> One </a>"##));
+        assert!(html.contains(
+            r##"<a href="#sec-2" data-section="sec-2" aria-current="location"> Two </a>"##
+        ));
+
+        let mut other = document(":toc:\n\n# One\n\n:table:[id=t]\n::|a");
+        assert!(!render(&other).contains("data-section"));
+        assert_eq!(
+            other.set_active_section("t".into()).unwrap_err().kind(),
+            &ErrorKind::ActiveSection("t".into())
+        );
+    }
+
+    #[test]
+    fn theme() {
+        let gloss_and_table = ":table:[id=t]\n::|a\n\n:gloss:[id=g]\n::a\n::b";
+        let html = render(&document(gloss_and_table));
+        assert!(!html.contains("theme-"));
+
+        let html = render(&document(&format!(
+            ":theme: bordered\n\n:theme: minimal\n\n{}",
+            gloss_and_table
+        )));
+        assert!(html.contains(r#"<table id="t" class=" theme-bordered">"#));
+        assert!(html.contains(r#"<div id="g" class="gloss  theme-bordered" "#));
+        assert!(!html.contains("theme-minimal"));
+    }
+
+    #[test]
+    fn auto_title() {
+        let html = render(&document("# *Intro*\n\n# Other"));
+        assert!(!html.contains("<title>"));
+
+        let html = render(&document(":autotitle:\n\n# *Intro*\n\n# Other"));
+        assert!(html.contains("<title> Intro </title>"));
+        assert!(html.contains(r#"<h1 class="title"> Intro </h1>"#));
+
+        let html = render(&document(":autotitle:\n\n:title: Grammar\n\n# Intro"));
+        assert!(html.contains("<title> Grammar </title>"));
+    }
+
+    #[test]
+    fn map_text() {
+        let mut document = document(
+            ":title: Tsa\n\n:replace:\n:ts: tsu\n\n\
+            #[id=tsa] Tsa\n\n\
+            Tsa *ts _tsi_ `ts`* :ts: :link:[https://ts.example, title=ts] :ref:[tsa]\n\n\
+            :list:\n::ts\n  ::ts",
+        );
+        document.map_text(|s| s.replace("ts", "c").replace("Ts", "C"));
+        let html = render(&document);
+        assert!(!html.contains("ts<") && !html.contains(">ts"));
+        assert!(html.contains("<title> Ca </title>"));
+        assert!(html.contains(
+            r#"<em class=" ">c <i class=" ">ci</i> <span class=" conlang">c</span></em> <span class=" "> cu</span>"#
+        ));
+        assert!(html.contains(r#"href="https://ts.example">c</a>"#));
+        assert!(html.contains(r##"href="#tsa""##));
+        assert!(html.contains("<li>c<ul>"));
+    }
+
+    #[test]
+    fn import_cross_references() {
+        let chapter = temp_file(
+            "chapter.txt",
+            "# Nouns\n\n:table:[id=nouns] Nouns\n::|a\n\nSee :ref:[verbs] and :ref:[intro].",
+        );
+        let html = render(&document(&format!(
+            "# Intro\n\n:table:[id=intro] Intro\n::|a\n\n:import: {}\n\n\
+            # Verbs\n\n:table:[id=verbs] Verbs\n::|a\n\nSee :ref:[nouns].",
+            chapter
+        )));
+        assert!(html.contains(r##"See <a class="reference " href="#verbs">table 3</a> and <a class="reference " href="#intro">table 1</a>."##));
+        assert!(html.contains(r##"See <a class="reference " href="#nouns">table 2</a>."##));
+        assert!(
+            html.contains(r#"<h1 id="sec-2" class=" "><span class="secnum">2.</span> Nouns </h1>"#)
+        );
+        assert!(
+            html.contains(r#"<h1 id="sec-3" class=" "><span class="secnum">3.</span> Verbs </h1>"#)
+        );
+    }
+}
@@ -1,6 +1,156 @@
-use std::io;
+use std::{error, fmt, io};
 
-pub use anyhow::{Error, Result};
+/// A `Result` whose error is the library's [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error returned by the public API of the library, for callers which need to tell what went
+/// wrong, and where, rather than just report it.
+///
+/// Displaying the error gives its outermost description; the more specific descriptions are its
+/// [`source`](error::Error::source)s.
+///
+/// The functions for reading, parsing, and writing documents, in [`document`](crate::document),
+/// [`input`](crate::input), and [`parse`](crate::parse), return this error. Internally, including
+/// in the `update_param` methods of blocks, errors are `anyhow::Error`s with [`ErrorKind`]s as
+/// context, which those functions convert.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    location: Location,
+    chain: anyhow::Error,
+}
+
+/// The location in the input at which an error occurred.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Location {
+    /// The name of the file the error occurred in, if it occurred in an imported file.
+    pub source: Option<String>,
+    /// The line on which the block the error occurred in starts, counting from 0.
+    pub line: Option<usize>,
+}
+
+impl Error {
+    /// What went wrong. This is the most specific description of the error, rather than the
+    /// [`Block`](ErrorKind::Block) or [`Source`](ErrorKind::Source) it occurred in, which are
+    /// given by [`location`](Error::location).
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Where in the input the error occurred, as far as is known.
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(chain: anyhow::Error) -> Error {
+        let mut kind = None;
+        let mut fallback = None;
+        let mut location = Location::default();
+        // the chain goes from the outermost context inwards, so the innermost information wins.
+        for cause in chain.chain() {
+            if let Some(error) = cause.downcast_ref::<Error>() {
+                // an error which was already converted, such as from adding an imported block
+                kind = Some(error.kind.clone());
+                location.source = error.location.source.clone().or(location.source);
+                location.line = error.location.line.or(location.line);
+            } else if let Some(cause) = cause
+                .downcast_ref::<ContextError>()
+                .map(|context| &context.kind)
+                .or_else(|| cause.downcast_ref::<ErrorKind>())
+                .or_else(|| {
+                    // errors while writing are wrapped in IO errors
+                    cause
+                        .downcast_ref::<io::Error>()
+                        .and_then(io::Error::get_ref)
+                        .and_then(|inner| inner.downcast_ref::<ErrorKind>())
+                })
+            {
+                match cause {
+                    ErrorKind::Block(line) => location.line = Some(*line),
+                    ErrorKind::Source(source) => location.source = Some(source.clone()),
+                    ErrorKind::WriteIo(line) => {
+                        location.line = Some(*line);
+                        kind = Some(cause.clone());
+                    }
+                    _ => kind = Some(cause.clone()),
+                }
+                fallback = Some(cause.clone());
+            }
+        }
+        Error {
+            // every error the library returns has an `ErrorKind`, so the fallback is never used.
+            kind: kind.or(fallback).unwrap_or(ErrorKind::Parse),
+            location,
+            chain,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        anyhow::Error::from(kind).into()
+    }
+}
+
+/// An error with an `ErrorKind` describing the circumstances it occurred in, such as the block it
+/// occurred in.
+///
+/// This is used instead of the contexts of `anyhow::Context`, so that every `ErrorKind` can be
+/// found when converting to an [`Error`].
+#[derive(Debug)]
+struct ContextError {
+    kind: ErrorKind,
+    source: anyhow::Error,
+}
+
+impl fmt::Display for ContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.kind, f)
+    }
+}
+
+impl error::Error for ContextError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// Adds an `ErrorKind` as context to errors, like `anyhow::Context`.
+pub(crate) trait Context<T> {
+    fn context(self, kind: ErrorKind) -> anyhow::Result<T>;
+
+    fn with_context(self, kind: impl FnOnce() -> ErrorKind) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> Context<T> for std::result::Result<T, E> {
+    fn context(self, kind: ErrorKind) -> anyhow::Result<T> {
+        self.with_context(|| kind)
+    }
+
+    fn with_context(self, kind: impl FnOnce() -> ErrorKind) -> anyhow::Result<T> {
+        self.map_err(|source| {
+            ContextError {
+                kind: kind(),
+                source: source.into(),
+            }
+            .into()
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.chain, f)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.chain.source()
+    }
+}
 
 #[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
 pub enum ErrorKind {
@@ -10,37 +160,98 @@ pub enum ErrorKind {
     EndOfBlock(EndOfBlockKind),
     #[error("Expected `{0}`, got `{1}`")]
     Expected(char, char),
-    #[error("Gloss line after postamble")]
-    GlossLine,
+    #[error(
+        "Gloss line on line {0} comes after a `nosplit` line following the gloss; `nosplit` lines \
+        must all come before or after the gloss lines, so further examples need a separate gloss"
+    )]
+    GlossLine(usize),
+    #[error("Line {0} is a second attribution line, but a gloss can only have one")]
+    GlossAttribution(usize),
+    #[error("Line {0} is a second attribution line, but a quotation can only have one")]
+    QuoteAttribution(usize),
+    #[error("Column {0} to highlight isn't in the gloss, which has {1} columns")]
+    HighlightColumn(usize, usize),
+    #[error("`:endgroup:` on line {0} doesn't end a group")]
+    EndGroup(usize),
+    #[error("`:endcolumns:` on line {0} doesn't end a multi-column layout")]
+    EndColumns(usize),
+    #[error("`:endif:` on line {0} doesn't end an `:if:` block")]
+    EndIf(usize),
+    #[error("`:if:` on line {0} isn't ended by an `:endif:`")]
+    UnclosedIf(usize),
+    #[error("Line {0} should start with `::`")]
+    LineMarker(usize),
     #[error("Parsing error")]
     Parse,
     #[error("Unknown parameter {0}")]
     Parameter(String),
+    #[error("Unexpected text `{0}` after parameter list")]
+    ParameterList(String),
+    #[error("Invalid value `{1}` for parameter {0}")]
+    ParameterValue(String, String),
     #[error("Duplicate ID {0}")]
     Id(String),
+    #[error("Duplicate {0} number {1}")]
+    Number(&'static str, usize),
     #[error("Duplicate replace directive {0}")]
     Replace(String),
-    #[error("Invalid UTF-8 in line {0}")]
-    Unicode(usize),
+    #[error("Reference to undefined ID {0}")]
+    UndefinedReference(String),
+    #[error("Reference to unnumbered element {0} with the `number` style")]
+    UnnumberedReference(String),
+    #[error("Undefined replace directive {0}")]
+    UndefinedReplace(String),
+    #[error("Footnote outside the text of a block, such as in the title of the document")]
+    Footnote,
+    #[error("Malformed link URL `{0}`")]
+    LinkUrl(String),
+    #[error("Bidirectional control character U+{0:04X} on line {1}")]
+    BidiControl(u32, usize),
+    #[error(
+        "Invalid UTF-8 in line {0} after byte {1} of the line; the input must be encoded as UTF-8"
+    )]
+    Unicode(usize, usize),
     #[error("An IO error occurred while reading line {0}")]
     ReadIo(usize),
+    #[error("In {0}")]
+    Source(String),
     #[error("File {0} not found")]
     FileNotFound(String),
+    #[error("Can't read file {0}, since file access is disabled")]
+    FileAccess(String),
+    #[error("Can't use {0}, since unsafe HTML is disabled")]
+    UnsafeHtml(String),
+    #[error("Invalid attribute name `{0}`")]
+    AttributeName(String),
+    #[error("Failed to create file {0}")]
+    FileCreate(String),
     #[error("An IO error occurred while writing block starting on line {0}")]
     WriteIo(usize),
     #[error("An IO error occurred while writing head matter")]
     WriteIoHead,
     #[error("An IO error occurred while writing tail matter")]
     WriteIoTail,
+    #[error("An IO error occurred while writing post-processed output")]
+    WriteIoPostProcessed,
+    #[error("The root of a table of contents, {0}, is not the ID of a heading")]
+    TocRoot(String),
+    #[error("The active section, {0}, is not the ID of a heading")]
+    ActiveSection(String),
+    #[error("Unknown command line argument {0}")]
+    Argument(String),
+    #[error("Malformed line `{0}` in numbering file")]
+    NumberingLine(String),
+    #[error("Invalid timestamp `{0}` in SOURCE_DATE_EPOCH")]
+    Timestamp(String),
 }
 
 impl ErrorKind {
-    pub fn input_error(err: io::Error, line: usize) -> Error {
-        let context = match err.kind() {
-            io::ErrorKind::InvalidData => ErrorKind::Unicode(line),
-            _ => ErrorKind::ReadIo(line),
-        };
-        Error::new(err).context(context)
+    pub fn input_error(err: io::Error, line: usize) -> anyhow::Error {
+        ContextError {
+            kind: ErrorKind::ReadIo(line),
+            source: err.into(),
+        }
+        .into()
     }
 }
 
@@ -1,4 +1,6 @@
-use std::fmt;
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::str;
 
 /// A structure which when formatted entity-encodes a minimal set of characters:
 ///
@@ -22,6 +24,24 @@ impl fmt::Display for Encoder<'_> {
     }
 }
 
+/// A structure which when formatted separates each pair of adjacent hyphens with a space, so that
+/// the text can be placed in an HTML comment without ending it early with `-->`.
+pub struct CommentEncoder<'a>(pub &'a str);
+
+impl fmt::Display for CommentEncoder<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut previous = None;
+        for c in self.0.chars() {
+            if c == '-' && previous == Some('-') {
+                f.write_char(' ')?;
+            }
+            f.write_char(c)?;
+            previous = Some(c);
+        }
+        Ok(())
+    }
+}
+
 fn get_entity(c: char) -> Option<&'static str> {
     match c {
         '"' => Some("quot"),
@@ -32,3 +52,95 @@ fn get_entity(c: char) -> Option<&'static str> {
         _ => None,
     }
 }
+
+/// A writer which replaces the characters written to it which match a predicate with numeric
+/// character references, such as `&#x1F600;`.
+///
+/// This must only be used for HTML, since the references are only meaningful in text and
+/// attribute values.
+#[derive(Debug)]
+pub struct CharRefWriter<W> {
+    inner: W,
+    /// The bytes of a character which has been partially written.
+    pending: Vec<u8>,
+    /// Whether a character is replaced.
+    encode: fn(char) -> bool,
+}
+
+impl<W: Write> CharRefWriter<W> {
+    pub fn new(inner: W, encode: fn(char) -> bool) -> CharRefWriter<W> {
+        CharRefWriter {
+            inner,
+            pending: Vec::new(),
+            encode,
+        }
+    }
+
+    /// Creates a writer which replaces every non-ASCII character, for output which must be pure
+    /// ASCII.
+    pub fn ascii(inner: W) -> CharRefWriter<W> {
+        CharRefWriter::new(inner, |c| !c.is_ascii())
+    }
+}
+
+impl<W: Write> Write for CharRefWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        // a character may be split between two calls, so only encode up to the last complete
+        // character.
+        let valid = match str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => {
+                self.pending.clear();
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        };
+        let mut encoded = String::with_capacity(valid);
+        for c in str::from_utf8(&self.pending[..valid]).unwrap().chars() {
+            if (self.encode)(c) {
+                write!(encoded, "&#x{:X};", u32::from(c)).unwrap();
+            } else {
+                encoded.push(c);
+            }
+        }
+        self.inner.write_all(encoded.as_bytes())?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_encoder() {
+        assert_eq!(CommentEncoder("a - b").to_string(), "a - b");
+        assert_eq!(CommentEncoder("a --> b").to_string(), "a - -> b");
+        assert_eq!(CommentEncoder("<!---").to_string(), "<!- - -");
+    }
+
+    #[test]
+    fn ascii_writer() {
+        let mut out = CharRefWriter::ascii(Vec::new());
+        write!(out, "<p title=\"é\">a 😀 b \u{E000}\u{F0000}</p>").unwrap();
+        assert_eq!(
+            String::from_utf8(out.inner).unwrap(),
+            "<p title=\"&#xE9;\">a &#x1F600; b &#xE000;&#xF0000;</p>"
+        );
+    }
+
+    #[test]
+    fn ascii_writer_split_character() {
+        let mut out = CharRefWriter::ascii(Vec::new());
+        for byte in "😀\u{F0000}".bytes() {
+            out.write_all(&[byte]).unwrap();
+        }
+        assert_eq!(out.inner, b"&#x1F600;&#xF0000;");
+    }
+}
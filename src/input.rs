@@ -1,13 +1,52 @@
-use std::io::{BufRead, Lines};
-use std::iter::Enumerate;
+use std::io::BufRead;
 
-use crate::errors::{ErrorKind, Result as EResult};
+use anyhow::Result as EResult;
+
+use crate::blocks::control::DefaultBlock;
+use crate::errors::{Error, ErrorKind, Result};
 use crate::parse::Block;
 
+/// How to handle bidirectional control characters in the input, which can make the output
+/// display differently from its contents.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BidiControls {
+    /// Remove the characters.
+    Strip,
+    /// Write the characters as their numeric character references (e.g. `&#x202E;`), so that they
+    /// are visible in the HTML source. They are kept in the input, and replaced when the document
+    /// is written, so that the references aren't parsed as text.
+    #[default]
+    Escape,
+    /// Keep the characters, with a warning.
+    Keep,
+}
+
+impl BidiControls {
+    /// Whether the character is an embedding, override, or isolate control character.
+    ///
+    /// Marks such as U+200F RIGHT-TO-LEFT MARK and joiners such as U+200D ZERO WIDTH JOINER are
+    /// not included, since they are needed to write many languages correctly.
+    pub fn is_control(c: char) -> bool {
+        matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+    }
+}
+
 #[derive(Debug)]
 pub struct Input<B> {
-    lines: Enumerate<Lines<B>>,
+    input: B,
+    /// The number of lines which have been read from the input so far.
+    lines_read: usize,
     buffer: Vec<char>,
+    /// The number of bytes which have been read from the input so far.
+    bytes_read: usize,
+    /// How to handle bidirectional control characters.
+    bidi_controls: BidiControls,
+    /// Whether blocks print their parameter lists to standard error as they are parsed.
+    debug_params: bool,
+    /// How blocks which aren't headings or block directives are parsed.
+    default_block: DefaultBlock,
+    /// Warnings about the input which has been read so far.
+    warnings: Vec<ErrorKind>,
 }
 
 impl<B> Input<B>
@@ -16,9 +55,71 @@ where
 {
     pub fn new(input: B) -> Input<B> {
         Input {
-            lines: input.lines().enumerate(),
+            input,
+            lines_read: 0,
             buffer: Vec::new(),
+            bytes_read: 0,
+            bidi_controls: BidiControls::default(),
+            debug_params: false,
+            default_block: DefaultBlock::default(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Sets how to handle bidirectional control characters.
+    pub fn set_bidi_controls(&mut self, bidi_controls: BidiControls) {
+        self.bidi_controls = bidi_controls;
+    }
+
+    /// Sets whether blocks print their parameter lists to standard error as they are parsed.
+    pub fn set_debug_params(&mut self, debug_params: bool) {
+        self.debug_params = debug_params;
+    }
+
+    /// Sets how blocks which are read afterwards are parsed if they aren't headings or block
+    /// directives.
+    pub fn set_default_block(&mut self, default_block: DefaultBlock) {
+        self.default_block = default_block;
+    }
+
+    /// Removes and returns the warnings about the input which has been read so far.
+    pub fn take_warnings(&mut self) -> Vec<ErrorKind> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// The number of bytes which have been read from the input so far, including line endings.
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+
+    /// Reads the next line from the input, without its line ending, along with its line number,
+    /// or returns `None` at the end of the input.
+    ///
+    /// A byte order mark at the start of the input is removed.
+    fn next_line(&mut self) -> EResult<Option<(usize, String)>> {
+        let line_number = self.lines_read;
+        let mut bytes = Vec::new();
+        let len = self
+            .input
+            .read_until(b'\n', &mut bytes)
+            .map_err(|e| ErrorKind::input_error(e, line_number))?;
+        if len == 0 {
+            return Ok(None);
+        }
+        self.lines_read += 1;
+        self.bytes_read += len;
+        if bytes.ends_with(b"\n") {
+            bytes.pop();
+            if bytes.ends_with(b"\r") {
+                bytes.pop();
+            }
         }
+        let mut line = String::from_utf8(bytes)
+            .map_err(|e| ErrorKind::Unicode(line_number, e.utf8_error().valid_up_to()))?;
+        if line_number == 0 && line.starts_with('\u{feff}') {
+            line.remove(0);
+        }
+        Ok(Some((line_number, line)))
     }
 
     /// Retrieves the next block from the input.
@@ -26,13 +127,11 @@ where
     /// Blocks are delimited by blank (all-whitespace) lines.
     ///
     /// An empty block signifies that the end of the input has been reached.
-    pub fn next_block(&mut self) -> EResult<Block> {
+    pub fn next_block(&mut self) -> Result<Block<'_>> {
         let mut start_line = None;
         // clear buffer
         self.buffer.clear();
-        for (line_number, line) in &mut self.lines {
-            // unwrap line
-            let line = line.map_err(|e| ErrorKind::input_error(e, line_number))?;
+        while let Some((line_number, line)) = self.next_line().map_err(Error::from)? {
             // blank lines
             if line.trim().is_empty() {
                 // if the buffer is empty, don't return anything
@@ -45,12 +144,30 @@ where
                     // if this is the first line of the block, set the start line
                     start_line = Some(line_number);
                 }
-                self.buffer.extend(line.chars());
+                for c in line.chars() {
+                    if !BidiControls::is_control(c) {
+                        self.buffer.push(c);
+                        continue;
+                    }
+                    match self.bidi_controls {
+                        BidiControls::Strip => {}
+                        // escaped when the document is written
+                        BidiControls::Escape => self.buffer.push(c),
+                        BidiControls::Keep => {
+                            self.buffer.push(c);
+                            self.warnings
+                                .push(ErrorKind::BidiControl(c as u32, line_number));
+                        }
+                    }
+                }
                 self.buffer.push('\n');
             }
         }
         // if we broke earlier, or if we've reached the end of the text, return the iterator.
-        Ok(Block::new(self.buffer.as_ref(), start_line))
+        let mut block = Block::new(self.buffer.as_ref(), start_line);
+        block.set_debug_params(self.debug_params);
+        block.set_default_block(self.default_block);
+        Ok(block)
     }
 }
 
@@ -118,6 +235,38 @@ mod tests {
         }
     }
 
+    fn first_block(input: &mut Input<&[u8]>) -> String {
+        let block = input.next_block().unwrap();
+        block.iter().collect()
+    }
+
+    #[test]
+    fn bidi_controls() {
+        let input_str = "a\u{202E}b\u{202D}c\u{200D}d\n\ne\u{2067}f".as_bytes();
+
+        let mut input = Input::new(input_str);
+        assert_eq!(first_block(&mut input), "a\u{202E}b\u{202D}c\u{200D}d\n");
+        assert!(input.take_warnings().is_empty());
+
+        let mut input = Input::new(input_str);
+        input.set_bidi_controls(BidiControls::Strip);
+        assert_eq!(first_block(&mut input), "abc\u{200D}d\n");
+        assert!(input.take_warnings().is_empty());
+
+        let mut input = Input::new(input_str);
+        input.set_bidi_controls(BidiControls::Keep);
+        assert_eq!(first_block(&mut input), "a\u{202E}b\u{202D}c\u{200D}d\n");
+        assert_eq!(first_block(&mut input), "e\u{2067}f\n");
+        assert_eq!(
+            input.take_warnings(),
+            [
+                ErrorKind::BidiControl(0x202E, 0),
+                ErrorKind::BidiControl(0x202D, 0),
+                ErrorKind::BidiControl(0x2067, 2),
+            ]
+        );
+    }
+
     #[test]
     fn no_final_newline() {
         let input_str = r#"block 1, line 1
@@ -137,4 +286,24 @@ mod tests {
             assert_eq!(block.start(), None);
         }
     }
+
+    #[test]
+    fn byte_order_mark() {
+        let mut input = Input::new("\u{feff}:title: A\r\n\r\n\u{feff}b".as_bytes());
+        assert_eq!(first_block(&mut input), ":title: A\n");
+        // only a mark at the start of the input is removed
+        assert_eq!(first_block(&mut input), "\u{feff}b\n");
+
+        let mut input = Input::new("\u{feff}:title: A".as_bytes());
+        let block = input.next_block().unwrap().parse().unwrap().unwrap();
+        assert!(block.kind.as_control().is_some());
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut input = Input::new(&b"a\n\nb\xE9c\n"[..]);
+        assert_eq!(first_block(&mut input), "a\n");
+        let err = input.next_block().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Unicode(2, 1));
+    }
 }
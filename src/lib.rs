@@ -0,0 +1,8 @@
+#[macro_use]
+pub mod html;
+pub mod blocks;
+pub mod document;
+pub mod errors;
+pub mod input;
+pub mod parse;
+pub mod text;
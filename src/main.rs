@@ -1,15 +1,14 @@
-#[macro_use]
-mod html;
-mod blocks;
-mod document;
-mod errors;
-mod input;
-mod parse;
-mod text;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use std::io;
-
-use errors::Result as EResult;
+use anyhow::{Context, Result as EResult};
+use conlang_fmt::document::{Document, DuplicateIds};
+use conlang_fmt::errors::ErrorKind;
+use conlang_fmt::html::CharRefWriter;
+use conlang_fmt::input::BidiControls;
 
 fn main() {
     if let Err(e) = main_result() {
@@ -19,20 +18,311 @@ fn main() {
     }
 }
 
+/// Command line options.
+#[derive(Debug)]
+struct Options {
+    /// Output the frequencies of words in conlang spans instead of the document.
+    word_freq: bool,
+    /// Write each section up to this level to its own file, instead of writing the document to
+    /// standard output.
+    split_by_level: Option<usize>,
+    /// Display the time the document was generated in a footer.
+    timestamp: bool,
+    /// The ID of the section to mark as the current section in tables of contents.
+    active_section: Option<String>,
+    /// The name of the input in error messages.
+    stdin_name: String,
+    /// Warn about malformed link URLs.
+    check_links: bool,
+    /// Output the `:todo:` notes in the document instead of the document.
+    list_todos: bool,
+    /// Replace all non-ASCII characters in the output with numeric character references.
+    ascii_only: bool,
+    /// Omit the `<meta name="generator">` tag.
+    no_generator: bool,
+    /// Omit the built-in classes of elements.
+    no_default_classes: bool,
+    /// Make blocks which can be referenced focusable.
+    focusable_targets: bool,
+    /// Disable reading files named in the document.
+    safe: bool,
+    /// Replace imported files which don't exist with placeholders, instead of stopping.
+    lenient_imports: bool,
+    /// How to handle bidirectional control characters in the input.
+    bidi_controls: BidiControls,
+    /// The directory to write the document and copies of its local files to, instead of writing
+    /// the document to standard output.
+    output_dir: Option<PathBuf>,
+    /// How to handle elements whose IDs are already used.
+    duplicate_ids: DuplicateIds,
+    /// Print each parameter list to standard error as it is parsed.
+    debug_params: bool,
+    /// A file to read the last numbers used by a previous document from, to continue its
+    /// numbering.
+    read_numbering: Option<PathBuf>,
+    /// A file to write the last numbers used by the document to, so that another document can
+    /// continue its numbering.
+    write_numbering: Option<PathBuf>,
+    /// The number of the first top-level section.
+    start_section_number: Option<usize>,
+    /// The number of the first table.
+    start_table_number: Option<usize>,
+    /// The number of the first gloss.
+    start_gloss_number: Option<usize>,
+    /// Output just the HTML of the blocks, without the head and tail of the document.
+    snippet: bool,
+    /// The variables to set, for `:if:` blocks.
+    defines: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            word_freq: false,
+            split_by_level: None,
+            timestamp: false,
+            active_section: None,
+            stdin_name: "<stdin>".into(),
+            check_links: false,
+            list_todos: false,
+            ascii_only: false,
+            no_generator: false,
+            no_default_classes: false,
+            focusable_targets: false,
+            safe: false,
+            lenient_imports: false,
+            bidi_controls: BidiControls::default(),
+            output_dir: None,
+            duplicate_ids: DuplicateIds::default(),
+            debug_params: false,
+            read_numbering: None,
+            write_numbering: None,
+            start_section_number: None,
+            start_table_number: None,
+            start_gloss_number: None,
+            snippet: false,
+            defines: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> EResult<Options> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--word-freq" => options.word_freq = true,
+                "--timestamp" => options.timestamp = true,
+                "--check-links" => options.check_links = true,
+                "--list-todos" => options.list_todos = true,
+                "--ascii-only" => options.ascii_only = true,
+                "--no-generator" => options.no_generator = true,
+                "--no-default-classes" => options.no_default_classes = true,
+                "--focusable-targets" => options.focusable_targets = true,
+                "--safe" => options.safe = true,
+                "--lenient-imports" => options.lenient_imports = true,
+                "--debug-params" => options.debug_params = true,
+                "--snippet" => options.snippet = true,
+                "--split-by-level" => {
+                    let level = args
+                        .next()
+                        .and_then(|level| level.parse().ok())
+                        // there are no sections at level 0 to split the document into
+                        .filter(|&level: &usize| level > 0)
+                        .ok_or(ErrorKind::Argument(arg))?;
+                    options.split_by_level = Some(level);
+                }
+                "--active-section" => {
+                    options.active_section = Some(args.next().ok_or(ErrorKind::Argument(arg))?);
+                }
+                "--bidi-controls" => {
+                    options.bidi_controls = match args.next().as_deref() {
+                        Some("strip") => BidiControls::Strip,
+                        Some("escape") => BidiControls::Escape,
+                        Some("keep") => BidiControls::Keep,
+                        _ => return Err(ErrorKind::Argument(arg).into()),
+                    };
+                }
+                "--duplicate-ids" => {
+                    options.duplicate_ids = match args.next().as_deref() {
+                        Some("error") => DuplicateIds::Error,
+                        Some("warn") => DuplicateIds::Warn,
+                        Some("suffix") => DuplicateIds::Suffix,
+                        _ => return Err(ErrorKind::Argument(arg).into()),
+                    };
+                }
+                "--read-numbering" => {
+                    options.read_numbering =
+                        Some(args.next().ok_or(ErrorKind::Argument(arg))?.into());
+                }
+                "--write-numbering" => {
+                    options.write_numbering =
+                        Some(args.next().ok_or(ErrorKind::Argument(arg))?.into());
+                }
+                "--start-section-number" | "--start-table-number" | "--start-gloss-number" => {
+                    let number = args
+                        .next()
+                        .and_then(|number| number.parse().ok())
+                        // numbering starts from 1
+                        .filter(|&number: &usize| number > 0)
+                        .ok_or_else(|| ErrorKind::Argument(arg.clone()))?;
+                    match arg.as_ref() {
+                        "--start-section-number" => options.start_section_number = Some(number),
+                        "--start-table-number" => options.start_table_number = Some(number),
+                        _ => options.start_gloss_number = Some(number),
+                    }
+                }
+                "--output-dir" => {
+                    options.output_dir = Some(args.next().ok_or(ErrorKind::Argument(arg))?.into());
+                }
+                "--define" => {
+                    options
+                        .defines
+                        .push(args.next().ok_or(ErrorKind::Argument(arg))?);
+                }
+                "--stdin-name" => {
+                    options.stdin_name = args.next().ok_or(ErrorKind::Argument(arg))?;
+                }
+                _ => return Err(ErrorKind::Argument(arg).into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
 fn main_result() -> EResult<()> {
+    let options = Options::from_args(env::args().skip(1))?;
     // for now, just read from stdin
     let stdin = io::stdin();
-    let mut input = input::Input::new(stdin.lock());
-    let mut document: document::Document = Default::default();
-    loop {
-        let mut block = input.next_block()?;
-        if let Some(block) = block.parse()? {
-            document.add_block(block)?;
-        } else {
-            break;
+    let mut document = Document::default();
+    document.set_bidi_controls(options.bidi_controls);
+    document.set_duplicate_ids(options.duplicate_ids);
+    document.set_debug_params(options.debug_params);
+    document.set_safe(options.safe);
+    document.set_lenient_imports(options.lenient_imports);
+    for variable in options.defines {
+        document.define(variable);
+    }
+    if let Some(path) = &options.read_numbering {
+        let numbering = fs::read_to_string(path)
+            .context(ErrorKind::FileNotFound(path.display().to_string()))?
+            .parse()
+            .context(ErrorKind::Source(path.display().to_string()))?;
+        document.set_numbering(numbering);
+    }
+    if let Some(number) = options.start_section_number {
+        document.set_start_section_number(number);
+    }
+    if let Some(number) = options.start_table_number {
+        document.set_start_table_number(number);
+    }
+    if let Some(number) = options.start_gloss_number {
+        document.set_start_gloss_number(number);
+    }
+    document
+        .read(stdin.lock(), None)
+        .context(ErrorKind::Source(options.stdin_name.clone()))?;
+    for warning in document.input_warnings() {
+        eprintln!("Warning: {warning}");
+    }
+    if let Some(path) = &options.write_numbering {
+        fs::write(path, document.numbering().to_string())
+            .context(ErrorKind::FileCreate(path.display().to_string()))?;
+    }
+    if options.check_links {
+        for warning in document.check_links() {
+            eprintln!("Warning: {warning}");
         }
     }
+    document.set_omit_generator(options.no_generator);
+    document.set_omit_default_classes(options.no_default_classes);
+    document.set_focusable_targets(options.focusable_targets);
+    if options.timestamp {
+        document.set_timestamp(timestamp()?);
+    }
+    if let Some(id) = options.active_section {
+        document.set_active_section(id)?;
+    }
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
-    document.write(&mut stdout)
+    if options.word_freq {
+        write_frequencies(&mut stdout, &document)?;
+        return Ok(());
+    }
+    if options.list_todos {
+        for (line, todo) in document.todos() {
+            writeln!(stdout, "{}:{}: {todo}", options.stdin_name, line + 1)?;
+        }
+        return Ok(());
+    }
+    if options.snippet {
+        document.write_fragment(&mut html_writer(stdout, options.ascii_only))?;
+        return Ok(());
+    }
+    let dir = options.output_dir.as_deref().unwrap_or(Path::new(""));
+    if let Some(output_dir) = &options.output_dir {
+        fs::create_dir_all(output_dir)
+            .context(ErrorKind::FileCreate(output_dir.display().to_string()))?;
+        document.copy_assets(output_dir)?;
+    }
+    if let Some(level) = options.split_by_level {
+        document.split_by_level(level);
+        for (i, file) in document.files().iter().enumerate() {
+            let mut w = create_html_file(&dir.join(&file.name), options.ascii_only)?;
+            document.write_file(&mut w, i)?;
+            w.flush().context(ErrorKind::WriteIoTail)?;
+        }
+        return Ok(());
+    }
+    if options.output_dir.is_some() {
+        let mut w = create_html_file(&dir.join("index.html"), options.ascii_only)?;
+        document.write(&mut w)?;
+        w.flush().context(ErrorKind::WriteIoTail)?;
+        return Ok(());
+    }
+    document.write(&mut html_writer(stdout, options.ascii_only))?;
+    Ok(())
+}
+
+/// Creates the file at the given path, for HTML output.
+fn create_html_file(path: &Path, ascii_only: bool) -> EResult<Box<dyn Write>> {
+    let file = File::create(path).context(ErrorKind::FileCreate(path.display().to_string()))?;
+    Ok(html_writer(BufWriter::new(file), ascii_only))
+}
+
+/// Wraps the writer for HTML output, replacing non-ASCII characters with character references if
+/// `ascii_only` is set.
+fn html_writer<'a>(w: impl Write + 'a, ascii_only: bool) -> Box<dyn Write + 'a> {
+    if ascii_only {
+        Box::new(CharRefWriter::ascii(w))
+    } else {
+        Box::new(w)
+    }
+}
+
+/// Gets the time to display in the footer, in seconds since the Unix epoch: the value of
+/// `SOURCE_DATE_EPOCH` if it is set, for reproducible builds, or the current time otherwise.
+fn timestamp() -> EResult<u64> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => Ok(epoch
+            .trim()
+            .parse()
+            .map_err(|_| ErrorKind::Timestamp(epoch))?),
+        Err(_) => Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())),
+    }
+}
+
+/// Writes the frequencies of words in conlang spans, from most to least frequent.
+fn write_frequencies(w: &mut impl Write, document: &Document) -> io::Result<()> {
+    let mut frequencies = document
+        .conlang_frequencies()
+        .into_iter()
+        .collect::<Vec<_>>();
+    frequencies.sort_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+    for (word, count) in frequencies {
+        writeln!(w, "{count}\t{word}")?;
+    }
+    Ok(())
 }
@@ -1,11 +1,11 @@
 use std::ops::Deref;
 
-use anyhow::Context;
+use anyhow::Result as EResult;
 
 use itertools::Itertools;
 
 use crate::blocks::{self, Parameter, UpdateParam};
-use crate::errors::{EndOfBlockKind, ErrorKind, Result as EResult};
+use crate::errors::{Context, EndOfBlockKind, Error, ErrorKind, Result};
 use crate::text;
 
 type OResult<T> = EResult<Option<T>>;
@@ -17,6 +17,15 @@ pub struct Block<'a> {
     /// The starting line number of the block, which is only defined for non-empty blocks.
     start: Option<usize>,
     idx: usize,
+    /// Whether whitespace in text is kept as written, rather than collapsed into a single space.
+    preserve_whitespace: bool,
+    /// Whether to print each parameter list to standard error as it is parsed.
+    debug_params: bool,
+    /// How the block is parsed if it isn't a heading or a block directive.
+    default_block: blocks::control::DefaultBlock,
+    /// Whether the last call to `text_until` removed whitespace from the end of its text, which
+    /// belongs after the element containing that text.
+    trailing_space: bool,
 }
 
 /// Update each object `$x` in order with the parameters returned by `$self.parameters()?`.
@@ -77,11 +86,30 @@ impl<'a> Block<'a> {
             slice,
             start,
             idx: 0,
+            preserve_whitespace: false,
+            debug_params: false,
+            default_block: Default::default(),
+            trailing_space: false,
         }
     }
 
+    /// Sets how the block is parsed if it isn't a heading or a block directive.
+    pub fn set_default_block(&mut self, default_block: blocks::control::DefaultBlock) {
+        self.default_block = default_block;
+    }
+
+    /// Sets whether to print each parameter list to standard error as it is parsed, with the line
+    /// the block starts on, for diagnosing quoting and escaping problems.
+    pub fn set_debug_params(&mut self, debug_params: bool) {
+        self.debug_params = debug_params;
+    }
+
     /// Parses the block.
-    pub fn parse(&mut self) -> OResult<blocks::Block> {
+    pub fn parse(&mut self) -> Result<Option<blocks::Block>> {
+        self.parse_block().map_err(Error::from)
+    }
+
+    fn parse_block(&mut self) -> OResult<blocks::Block> {
         // skip leading whitespace
         self.skip_whitespace();
         // save the position of the first non-whitespace character; if we need to rewind, this is
@@ -90,6 +118,8 @@ impl<'a> Block<'a> {
         Ok(Some(match self.next() {
             Some(':') => match self.directive()?.as_ref() {
                 "title" => self.parse_title()?,
+                "autotitle" => blocks::control::DocumentControl::AutoTitle.into(),
+                "sharednumbers" => blocks::control::DocumentControl::SharedNumbers.into(),
                 "author" => self.parse_author()?,
                 "description" => self.parse_description()?,
                 "style" => self.parse_stylesheet()?,
@@ -99,7 +129,33 @@ impl<'a> Block<'a> {
                 "list" => self.parse_list()?,
                 "table" => self.parse_table()?,
                 "gloss" => self.parse_gloss()?,
+                "bibliography" => self.parse_bibliography()?,
+                "text" => self.parse_text_run()?,
+                "pre" => self.parse_preformatted()?,
+                "quote" => self.parse_quote()?,
+                "group" => self.parse_group()?,
+                "endgroup" => self.parse_end_group(false)?,
+                "endcolumns" => self.parse_end_group(true)?,
+                "columns" => self.parse_columns()?,
                 "replace" => self.parse_replace_block()?,
+                "refprefix" => self.parse_reference_prefixes()?,
+                "idprefix" => self.parse_id_prefixes()?,
+                "arialabel" => self.parse_aria_labels()?,
+                "numberstyle" => self.parse_number_styles()?,
+                "abbr" => self.parse_abbreviations()?,
+                "undefined" => self.parse_undefined()?,
+                "formatting" => self.parse_formatting()?,
+                "doctype" => self.parse_doctype()?,
+                "theme" => self.parse_theme()?,
+                "baseurl" => self.parse_base_url()?,
+                "maxheading" => self.parse_max_heading()?,
+                "htmlattr" => self.parse_html_attributes()?,
+                "variantsep" => self.parse_variant_separator()?,
+                "defaultblock" => self.parse_default_block()?,
+                "define" => self.parse_define()?,
+                "autotoc" => self.parse_auto_toc()?,
+                "if" => self.parse_if()?,
+                "endif" => self.parse_end_if()?,
                 // any other directive is an inline directive; rewind and parse the block as a
                 // paragraph
                 _ => self.parse_paragraph(start)?,
@@ -146,6 +202,134 @@ impl<'a> Block<'a> {
         Ok(blocks::control::DocumentControl::Import(text).into())
     }
 
+    fn parse_undefined(&mut self) -> EResult<blocks::Block> {
+        let undefined = match self.word_rest().as_ref() {
+            "span" => blocks::control::Undefined::Span,
+            "text" => blocks::control::Undefined::Text,
+            "error" => blocks::control::Undefined::Error,
+            value => {
+                return Err(ErrorKind::ParameterValue("undefined".into(), value.into()))
+                    .context(ErrorKind::Block(self.start.unwrap()))
+            }
+        };
+        Ok(blocks::control::DocumentControl::Undefined(undefined).into())
+    }
+
+    fn parse_formatting(&mut self) -> EResult<blocks::Block> {
+        let formatting = match self.word_rest().as_ref() {
+            "tags" => blocks::control::Formatting::Tags,
+            "classes" => blocks::control::Formatting::Classes,
+            value => {
+                return Err(ErrorKind::ParameterValue("formatting".into(), value.into()))
+                    .context(ErrorKind::Block(self.start.unwrap()))
+            }
+        };
+        Ok(blocks::control::DocumentControl::Formatting(formatting).into())
+    }
+
+    fn parse_default_block(&mut self) -> EResult<blocks::Block> {
+        let default_block = match self.word_rest().as_ref() {
+            "paragraph" => blocks::control::DefaultBlock::Paragraph,
+            "raw" => blocks::control::DefaultBlock::Raw,
+            value => {
+                return Err(ErrorKind::ParameterValue(
+                    "defaultblock".into(),
+                    value.into(),
+                ))
+                .context(ErrorKind::Block(self.start.unwrap()))
+            }
+        };
+        Ok(blocks::control::DocumentControl::DefaultBlock(default_block).into())
+    }
+
+    fn parse_max_heading(&mut self) -> EResult<blocks::Block> {
+        let value = self.word_rest();
+        let mut words = value.split_whitespace();
+        let max_heading = match (
+            words.next().and_then(|level| level.parse().ok()),
+            words.next(),
+            words.next(),
+        ) {
+            (Some(level), None, None) => blocks::control::MaxHeading {
+                level,
+                numbered: true,
+            },
+            (Some(level), Some("nonumber"), None) => blocks::control::MaxHeading {
+                level,
+                numbered: false,
+            },
+            _ => {
+                return Err(ErrorKind::ParameterValue("maxheading".into(), value))
+                    .context(ErrorKind::Block(self.start.unwrap()))
+            }
+        };
+        Ok(blocks::control::DocumentControl::MaxHeading(max_heading).into())
+    }
+
+    fn parse_doctype(&mut self) -> EResult<blocks::Block> {
+        let doctype = self.word_rest();
+        Ok(blocks::control::DocumentControl::Doctype(doctype).into())
+    }
+
+    fn parse_theme(&mut self) -> EResult<blocks::Block> {
+        let theme = self.word_rest();
+        Ok(blocks::control::DocumentControl::Theme(theme).into())
+    }
+
+    fn parse_base_url(&mut self) -> EResult<blocks::Block> {
+        let base_url = self.word_rest();
+        Ok(blocks::control::DocumentControl::BaseUrl(base_url).into())
+    }
+
+    fn parse_variant_separator(&mut self) -> EResult<blocks::Block> {
+        let separator = self.word_rest();
+        Ok(blocks::control::DocumentControl::VariantSeparator(separator).into())
+    }
+
+    fn parse_auto_toc(&mut self) -> EResult<blocks::Block> {
+        let auto_toc = match self.word_rest().as_ref() {
+            "after-title" => blocks::control::AutoToc::AfterTitle,
+            "none" => blocks::control::AutoToc::None,
+            value => {
+                return Err(ErrorKind::ParameterValue("autotoc".into(), value.into()))
+                    .context(ErrorKind::Block(self.start.unwrap()))
+            }
+        };
+        Ok(blocks::control::DocumentControl::AutoToc(auto_toc).into())
+    }
+
+    fn parse_define(&mut self) -> EResult<blocks::Block> {
+        let variable = self.word_rest();
+        Ok(blocks::control::DocumentControl::Define(variable).into())
+    }
+
+    fn parse_if(&mut self) -> EResult<blocks::Block> {
+        let variable = self.word_rest();
+        Ok(match variable.strip_prefix('!') {
+            Some(variable) => blocks::control::DocumentControl::If {
+                variable: variable.trim_start().into(),
+                negated: true,
+            },
+            None => blocks::control::DocumentControl::If {
+                variable,
+                negated: false,
+            },
+        }
+        .into())
+    }
+
+    fn parse_end_if(&mut self) -> EResult<blocks::Block> {
+        Ok(blocks::Block {
+            kind: Box::new(blocks::control::DocumentControl::EndIf),
+            common: blocks::BlockCommon::new(self.start.unwrap()),
+        })
+    }
+
+    fn parse_html_attributes(&mut self) -> EResult<blocks::Block> {
+        let attributes = self.directive_lines()?;
+        Ok(blocks::control::DocumentControl::HtmlAttributes(attributes).into())
+    }
+
     fn parse_toc(&mut self) -> EResult<blocks::Block> {
         let mut toc = blocks::contents::Contents::new();
         let mut common = blocks::BlockCommon::new(self.start.unwrap());
@@ -161,12 +345,12 @@ impl<'a> Block<'a> {
         let mut list = blocks::list::List::new();
         let mut common = blocks::BlockCommon::new(self.start.unwrap());
         update_multiple!(self, list, common);
+        let unit = list.indent.unwrap_or(1);
         while self.idx < self.len() {
             let indent = self.skip_whitespace_virtual() - self.idx;
             self.idx += indent + 2;
-            let mut item = blocks::list::ListItem::new();
-            self.text_until_hard_line(&mut item.text)?;
-            self.list_tree(indent, &mut item.sublist)?;
+            let mut item = self.list_item()?;
+            self.list_tree(indent / unit, unit, &mut item.sublist)?;
             list.items.push(item);
         }
         Ok(blocks::Block {
@@ -179,6 +363,7 @@ impl<'a> Block<'a> {
         let mut table = blocks::table::Table::new();
         let mut common = blocks::BlockCommon::new(self.start.unwrap());
         update_multiple!(self, table, common);
+        let separator = table.separator;
         self.text_until_char(&mut table.title, '\n')?;
         // put the newline back on the stack, since it's needed for `match_hard_line`
         self.idx -= 1;
@@ -186,7 +371,7 @@ impl<'a> Block<'a> {
         while let Some(c) = self.next() {
             match c {
                 // new cell
-                '|' => {
+                c if c == separator => {
                     let mut col = blocks::table::Column::new();
                     update_multiple!(self, col);
                     table.columns.push(col);
@@ -197,7 +382,7 @@ impl<'a> Block<'a> {
                 c if c.is_whitespace() => {}
                 // error
                 c => {
-                    return Err(ErrorKind::Expected('|', c))
+                    return Err(ErrorKind::Expected(separator, c))
                         .context(ErrorKind::Block(self.start.unwrap()));
                 }
             }
@@ -206,32 +391,39 @@ impl<'a> Block<'a> {
         // table
         while self.peek().is_some() {
             self.skip_whitespace();
-            // skip until after the double colon
-            self.idx += 2;
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect_line_marker()?;
             let mut row = blocks::table::Row::new();
             update_multiple!(self, row);
             // match the cells
             while let Some(c) = self.next() {
                 match c {
                     // new cell
-                    '|' => {
+                    c if c == separator => {
+                        // a cell at the start of a line is really the start of a row which is
+                        // missing its marker.
+                        if !row.cells.is_empty() && self.at_line_start(self.idx - 1) {
+                            return Err(ErrorKind::LineMarker(self.line()))
+                                .context(ErrorKind::Block(self.start.unwrap()));
+                        }
                         let mut cell = blocks::table::Cell::new();
                         update_multiple!(self, cell);
                         self.text_until(&mut cell.text, |slf, c| {
-                            c == '|' || slf.match_hard_line(c)
+                            c == separator || slf.match_hard_line(c)
                         })?;
-                        // rewind to put the pipe or newline back
+                        // rewind to put the separator or newline back
                         self.idx -= 1;
                         row.cells.push(cell);
-                        match self.peek() {
-                            Some('|') => {}
-                            _ => break,
+                        if self.peek() != Some(separator) {
+                            break;
                         }
                     }
                     '\n' if self.match_hard_line('\n') => break,
                     c if c.is_whitespace() => {}
                     c => {
-                        return Err(ErrorKind::Expected('|', c))
+                        return Err(ErrorKind::Expected(separator, c))
                             .context(ErrorKind::Block(self.start.unwrap()));
                     }
                 }
@@ -256,13 +448,41 @@ impl<'a> Block<'a> {
         // gloss
         while self.peek().is_some() {
             self.skip_whitespace();
-            // skip until after the double colon
-            self.idx += 2;
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect_line_marker()?;
             let mut class = String::new();
             let mut kind = blocks::gloss::GlossLineType::Split;
-            update_multiple!(self, kind, class);
+            let mut tier = blocks::gloss::GlossTier::Untiered;
+            update_multiple!(self, kind, tier, class);
+            if tier == blocks::gloss::GlossTier::Translation {
+                // the translation isn't split into words, and is displayed after the gloss lines
+                // wherever it's written
+                let mut line = Default::default();
+                self.text_until_hard_line(&mut line)?;
+                if !class.is_empty() {
+                    line = line.with_class(class);
+                }
+                gloss.translation.push(line);
+                continue;
+            }
             // check whether it's a nosplit:
             match kind {
+                blocks::gloss::GlossLineType::Attribution => {
+                    // the attribution isn't split into words, and is displayed at the end of the
+                    // gloss wherever it's written
+                    if gloss.attribution.is_some() {
+                        return Err(ErrorKind::GlossAttribution(self.line()))
+                            .context(ErrorKind::Block(self.start.unwrap()));
+                    }
+                    let mut line = Default::default();
+                    self.text_until_hard_line(&mut line)?;
+                    if !class.is_empty() {
+                        line = line.with_class(class);
+                    }
+                    gloss.attribution = Some(line);
+                }
                 blocks::gloss::GlossLineType::NoSplit => {
                     let mut line = Default::default();
                     // add the rest of the line
@@ -279,15 +499,17 @@ impl<'a> Block<'a> {
                         gloss.postamble.push(line);
                     }
                 }
-                blocks::gloss::GlossLineType::Split => {
+                blocks::gloss::GlossLineType::Split | blocks::gloss::GlossLineType::Tooltip => {
                     // check if we've already entered the postamble; a gloss line here
                     // is an error
                     if !gloss.postamble.is_empty() {
-                        return Err(ErrorKind::GlossLine)
+                        return Err(ErrorKind::GlossLine(self.line()))
                             .context(ErrorKind::Block(self.start.unwrap()));
                     }
                     let mut line = blocks::gloss::GlossLine::new();
                     line.class = class;
+                    line.tooltip = kind == blocks::gloss::GlossLineType::Tooltip;
+                    line.tier = tier;
                     while let Some(c) = self.next() {
                         match c {
                             // break if we're at a hard line break
@@ -296,13 +518,24 @@ impl<'a> Block<'a> {
                             c if c.is_whitespace() => {}
                             // non-whitespace; start a new word
                             _ => {
-                                let mut word = Default::default();
+                                let mut word = text::Text::new();
                                 // rewind, since we want to include the character we
                                 // matched
                                 self.idx -= 1;
-                                self.text_until(&mut word, |_, c| c.is_whitespace())?;
-                                // rewind, since `text_until` consumes the whitespace
-                                self.idx -= 1;
+                                loop {
+                                    self.text_until(&mut word, |this, c| {
+                                        c.is_whitespace() || (c == '~' && this.peek() != Some('{'))
+                                    })?;
+                                    // rewind, since `text_until` consumes the whitespace
+                                    self.idx -= 1;
+                                    // a `~` joins two parts of a word with a non-breaking space
+                                    if let Some('~') = self.peek() {
+                                        self.idx += 1;
+                                        word.push(String::from("\u{a0}"));
+                                    } else {
+                                        break;
+                                    }
+                                }
                                 line.push(word);
                             }
                         }
@@ -311,12 +544,47 @@ impl<'a> Block<'a> {
                 }
             }
         }
+        let columns = gloss.columns();
+        if let Some(&column) = gloss
+            .highlight
+            .iter()
+            .find(|&&column| column == 0 || column > columns)
+        {
+            return Err(ErrorKind::HighlightColumn(column, columns))
+                .context(ErrorKind::Block(self.start.unwrap()));
+        }
         Ok(blocks::Block {
             kind: Box::new(gloss),
             common,
         })
     }
 
+    fn parse_bibliography(&mut self) -> EResult<blocks::Block> {
+        let mut bibliography = blocks::bibliography::Bibliography::new();
+        let mut common = blocks::BlockCommon::new(self.start.unwrap());
+        update_multiple!(self, bibliography, common);
+        self.text_until_hard_line(&mut bibliography.title)?;
+        // now we've matched a hard line; time to start constructing the entries
+        while self.peek().is_some() {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect_line_marker()?;
+            let mut entry = blocks::bibliography::Entry::new();
+            update_multiple!(self, entry);
+            self.text_until_hard_line(&mut entry.title)?;
+            bibliography.entries.push(entry);
+        }
+        if bibliography.sorted {
+            bibliography.sort();
+        }
+        Ok(blocks::Block {
+            kind: Box::new(bibliography),
+            common,
+        })
+    }
+
     fn parse_replace_block(&mut self) -> EResult<blocks::Block> {
         let mut replacements = blocks::replacements::Replacements::new();
         let mut common = blocks::BlockCommon::new(self.start.unwrap());
@@ -336,6 +604,51 @@ impl<'a> Block<'a> {
         })
     }
 
+    fn parse_reference_prefixes(&mut self) -> EResult<blocks::Block> {
+        let prefixes = self.directive_lines()?;
+        Ok(blocks::control::DocumentControl::ReferencePrefixes(prefixes).into())
+    }
+
+    fn parse_id_prefixes(&mut self) -> EResult<blocks::Block> {
+        let prefixes = self.directive_lines()?;
+        Ok(blocks::control::DocumentControl::IdPrefixes(prefixes).into())
+    }
+
+    fn parse_aria_labels(&mut self) -> EResult<blocks::Block> {
+        let labels = self.directive_lines()?;
+        Ok(blocks::control::DocumentControl::AriaLabels(labels).into())
+    }
+
+    fn parse_number_styles(&mut self) -> EResult<blocks::Block> {
+        let mut styles = Vec::new();
+        self.skip_whitespace();
+        while let Some(':') = self.next() {
+            let kind = self.directive()?;
+            if kind != "table" && kind != "gloss" {
+                return Err(ErrorKind::Parameter(kind))
+                    .context(ErrorKind::Block(self.start.unwrap()));
+            }
+            let mut name = String::new();
+            while let Some(c) = self.next() {
+                if c == '\n' {
+                    break;
+                }
+                name.push(c);
+            }
+            let name = name.trim();
+            let style = blocks::control::NumberStyle::from_name(name)
+                .ok_or_else(|| ErrorKind::ParameterValue(kind.clone(), name.into()))
+                .context(ErrorKind::Block(self.start.unwrap()))?;
+            styles.push((kind, style));
+        }
+        Ok(blocks::control::DocumentControl::NumberStyles(styles).into())
+    }
+
+    fn parse_abbreviations(&mut self) -> EResult<blocks::Block> {
+        let abbreviations = self.directive_lines()?;
+        Ok(blocks::control::DocumentControl::Abbreviations(abbreviations).into())
+    }
+
     fn parse_heading(&mut self, start: usize) -> EResult<blocks::Block> {
         // count the `#`s
         while let Some('#') = self.next() {}
@@ -354,7 +667,117 @@ impl<'a> Block<'a> {
         })
     }
 
+    fn parse_text_run(&mut self) -> EResult<blocks::Block> {
+        let mut text = text::Text::new();
+        let common = blocks::BlockCommon::new(self.start.unwrap());
+        self.skip_whitespace();
+        self.text_rest(&mut text)?;
+        Ok(blocks::Block {
+            kind: Box::new(blocks::run::TextRun(text)),
+            common,
+        })
+    }
+
+    fn parse_group(&mut self) -> EResult<blocks::Block> {
+        let mut group = blocks::group::Group::Start(Default::default());
+        let mut common = blocks::BlockCommon::new(self.start.unwrap());
+        update_multiple!(self, group, common);
+        Ok(blocks::Block {
+            kind: Box::new(group),
+            common,
+        })
+    }
+
+    fn parse_columns(&mut self) -> EResult<blocks::Block> {
+        let mut group = blocks::group::Group::Start(Default::default());
+        let mut common = blocks::BlockCommon::new(self.start.unwrap());
+        update_multiple!(self, group, common);
+        let columns = self.word_rest();
+        let columns = if columns.is_empty() {
+            2
+        } else {
+            match columns.parse() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    return Err(ErrorKind::ParameterValue("columns".into(), columns))
+                        .context(ErrorKind::Block(self.start.unwrap()))
+                }
+            }
+        };
+        if let blocks::group::Group::Start(start) = &mut group {
+            start.columns = Some(columns);
+        }
+        Ok(blocks::Block {
+            kind: Box::new(group),
+            common,
+        })
+    }
+
+    fn parse_end_group(&mut self, columns: bool) -> EResult<blocks::Block> {
+        Ok(blocks::Block {
+            kind: Box::new(blocks::group::Group::End { columns }),
+            common: blocks::BlockCommon::new(self.start.unwrap()),
+        })
+    }
+
+    fn parse_preformatted(&mut self) -> EResult<blocks::Block> {
+        let mut text = text::Text::new();
+        let mut common = blocks::BlockCommon::new(self.start.unwrap());
+        update_multiple!(self, common);
+        self.skip_whitespace();
+        self.preserve_whitespace = true;
+        self.text_rest(&mut text)?;
+        self.preserve_whitespace = false;
+        Ok(blocks::Block {
+            kind: Box::new(blocks::pre::Preformatted(text)),
+            common,
+        })
+    }
+
+    fn parse_quote(&mut self) -> EResult<blocks::Block> {
+        let mut quote = blocks::quote::Quote::new();
+        let mut common = blocks::BlockCommon::new(self.start.unwrap());
+        update_multiple!(self, quote, common);
+        // the text on the same line as the directive is the first paragraph
+        let mut first = text::Text::new();
+        self.text_until_hard_line(&mut first)?;
+        if !first.0.is_empty() {
+            quote.paragraphs.push(first);
+        }
+        // each line after that is a paragraph or the attribution
+        while self.peek().is_some() {
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect_line_marker()?;
+            let mut class = String::new();
+            let mut kind = blocks::quote::QuoteLine::Paragraph;
+            update_multiple!(self, kind, class);
+            if kind == blocks::quote::QuoteLine::Attribution && quote.attribution.is_some() {
+                return Err(ErrorKind::QuoteAttribution(self.line()))
+                    .context(ErrorKind::Block(self.start.unwrap()));
+            }
+            let mut line = text::Text::new();
+            self.text_until_hard_line(&mut line)?;
+            if !class.is_empty() {
+                line = line.with_class(class);
+            }
+            match kind {
+                blocks::quote::QuoteLine::Paragraph => quote.paragraphs.push(line),
+                blocks::quote::QuoteLine::Attribution => quote.attribution = Some(line),
+            }
+        }
+        Ok(blocks::Block {
+            kind: Box::new(quote),
+            common,
+        })
+    }
+
     fn parse_paragraph(&mut self, start: usize) -> EResult<blocks::Block> {
+        if self.default_block == blocks::control::DefaultBlock::Raw {
+            return Ok(self.parse_raw());
+        }
         self.idx = start;
         let mut text = text::Text::new();
         let common = blocks::BlockCommon::new(self.start.unwrap());
@@ -365,25 +788,75 @@ impl<'a> Block<'a> {
         })
     }
 
-    /// Recursively appends list items to the given vector
+    /// Parses the whole block as raw lines, without any parsing of its text.
+    fn parse_raw(&mut self) -> blocks::Block {
+        let text = self.slice.iter().collect::<String>();
+        let raw = blocks::raw::Raw {
+            lines: text.lines().map(String::from).collect(),
+        };
+        self.idx = self.len();
+        blocks::Block {
+            kind: Box::new(raw),
+            common: blocks::BlockCommon::new(self.start.unwrap()),
+        }
+    }
+
+    /// Parses the parameters and text of a list item, after its `::`.
+    ///
+    /// A parameter list is only recognized if it starts with `id=`, so that an item starting with
+    /// brackets, such as a phonetic transcription like `[a]`, keeps them as text.
+    fn list_item(&mut self) -> EResult<blocks::list::ListItem> {
+        let mut item = blocks::list::ListItem::new();
+        if self.slice[self.idx..].starts_with(&['[', 'i', 'd', '=']) {
+            update_multiple!(self, item);
+        }
+        // skip whitespace before the text, but not the end of the line
+        while let Some(' ' | '\t') = self.peek() {
+            self.idx += 1;
+        }
+        self.text_until_hard_line(&mut item.text)?;
+        Ok(item)
+    }
+
+    /// Recursively appends list items to the given vector, while they are nested deeper than
+    /// `last_level`. The level of an item is its indentation divided by `unit`.
     fn list_tree(
         &mut self,
-        last_indent: usize,
+        last_level: usize,
+        unit: usize,
         parent: &mut Vec<blocks::list::ListItem>,
     ) -> EResult<()> {
         loop {
             let indent = self.skip_whitespace_virtual() - self.idx;
-            if indent <= last_indent {
+            let level = indent / unit;
+            if level <= last_level {
                 return Ok(());
             }
             self.idx += indent + 2;
-            let mut item = blocks::list::ListItem::new();
-            self.text_until_hard_line(&mut item.text)?;
-            self.list_tree(indent, &mut item.sublist)?;
+            let mut item = self.list_item()?;
+            self.list_tree(level, unit, &mut item.sublist)?;
             parent.push(item);
         }
     }
 
+    /// Returns a list of lines each consisting of a directive followed by text, skipping the
+    /// whitespace between the directive and the text.
+    fn directive_lines(&mut self) -> EResult<Vec<(String, text::Text)>> {
+        let mut lines = Vec::new();
+        self.skip_whitespace();
+        while let Some(':') = self.next() {
+            let directive = self.directive()?;
+            // skip whitespace between the directive and the text, but not the end of the line
+            while let Some(' ' | '\t') = self.peek() {
+                self.idx += 1;
+            }
+            let mut text = text::Text::new();
+            self.text_until_char(&mut text, '\n')?;
+            lines.push((directive, text));
+        }
+        Ok(lines)
+    }
+
     /// Returns a directive as a string, assuming the first `:` has already been parsed.
     fn directive(&mut self) -> EResult<String> {
         let mut directive = String::new();
@@ -414,7 +887,16 @@ impl<'a> Block<'a> {
                 loop {
                     match self.expect(']')? {
                         // end of the parameter list
-                        ']' => return Ok(params),
+                        ']' => {
+                            if self.debug_params {
+                                eprintln!(
+                                    "Parameters in block starting on line {}: [{}]",
+                                    self.start.unwrap(),
+                                    params.iter().format(", ")
+                                );
+                            }
+                            return Ok(params);
+                        }
                         // something else: it's a parameter
                         _ => {
                             // rewind, since the character we matched might be part of the
@@ -453,7 +935,8 @@ impl<'a> Block<'a> {
                 // end of the parameter list; return what we have so far, but keep the `]` on the
                 // stack.
                 ']' => break,
-                // get the next character, whatever it may be.
+                // get the next character, whatever it may be. This includes `=` and `,`, which
+                // are part of the name rather than ending it when escaped.
                 '\\' => {
                     self.idx += 1;
                     param_builder
@@ -565,6 +1048,15 @@ impl<'a> Block<'a> {
         }
     }
 
+    /// Returns the rest of the block as a string, without any leading or trailing whitespace.
+    fn word_rest(&mut self) -> String {
+        let word = self.slice[self.idx.min(self.len())..]
+            .iter()
+            .collect::<String>();
+        self.idx = self.len();
+        word.trim().into()
+    }
+
     /// Appends elements to the given `text::Text` object up until the end of the block.
     fn text_rest(&mut self, text: &mut text::Text) -> EResult<()> {
         // never break
@@ -605,16 +1097,33 @@ impl<'a> Block<'a> {
 
     /// Appends elements to the given `text::Text` object up until the character matching the
     /// specified predicate not contained in another element, or until the end of the block.
+    ///
+    /// Whitespace next to an inline element is kept outside of it, in the surrounding text, so a
+    /// space before or after an element in the source is always a single space in the output, and
+    /// an element with no whitespace next to it is never given any. In particular, whitespace
+    /// just before the closing delimiter of an element (e.g. a line break in `*a\n*`) is moved to
+    /// after the element (and its parameters), rather than being kept inside it.
     fn text_until(
         &mut self,
         text: &mut text::Text,
         predicate: impl Fn(&Self, char) -> bool,
     ) -> EResult<()> {
         let mut buffer = String::new();
+        // whether the buffer ends with a space which stands for whitespace in the source (rather
+        // than an escaped space)
+        let mut collapsed_space = false;
+        self.trailing_space = false;
         while let Some(c) = self.next() {
+            let after_space = std::mem::take(&mut collapsed_space);
             match c {
                 // the specified character was found, break
-                c if predicate(self, c) => break,
+                c if predicate(self, c) => {
+                    if after_space && buffer.ends_with(' ') {
+                        buffer.pop();
+                        self.trailing_space = true;
+                    }
+                    break;
+                }
                 // bracketed text
                 '{' => {
                     push_and_renew!(buffer: String::new(), text);
@@ -626,8 +1135,45 @@ impl<'a> Block<'a> {
                     text.push(match self.directive()?.as_ref() {
                         // cross reference
                         "ref" => self.simple_inline(text::InlineType::reference())?,
+                        // citation
+                        "cite" => self.simple_inline(text::InlineType::citation())?,
+                        // numeric cross reference
+                        "numref" => self.simple_inline(text::InlineType::number_reference())?,
+                        // reference to whether a block is above or below
+                        "relref" => {
+                            let id = self.directive()?;
+                            self.simple_inline(text::InlineType::relative_reference(id))?
+                        }
                         // link
-                        "link" => self.simple_inline(text::InlineType::link())?,
+                        "link" => self.link_inline()?,
+                        // gloss abbreviation
+                        "gl" => {
+                            let abbreviation = self.directive()?;
+                            self.simple_inline(text::InlineType::GlossAbbreviation(abbreviation))?
+                        }
+                        // note of something to do
+                        "todo" => self.todo_inline()?,
+                        // footnote
+                        "fn" => self.footnote_inline()?,
+                        // text without any formatting
+                        "raw" => self.verbatim_inline()?,
+                        // note written as an HTML comment
+                        "comment" => self.comment_inline()?,
+                        // document metadata
+                        "doctitle" => {
+                            self.simple_inline(text::InlineType::Metadata(text::Metadata::Title))?
+                        }
+                        "docauthor" => {
+                            self.simple_inline(text::InlineType::Metadata(text::Metadata::Author))?
+                        }
+                        "docdate" => {
+                            self.simple_inline(text::InlineType::Metadata(text::Metadata::Date))?
+                        }
+                        // variant forms
+                        "var-of" => self.variants_inline()?,
+                        // phonetic and phonemic transcriptions
+                        "phon" => self.bracketed_inline('[', ']', "ipa phonetic")?,
+                        "phone" | "phonemic" => self.bracketed_inline('/', '/', "ipa phonemic")?,
                         // replacement
                         repl => self.simple_inline(text::InlineType::Replace(repl.into()))?,
                     });
@@ -650,6 +1196,16 @@ impl<'a> Block<'a> {
                         text::InlineType::Bold,
                     )?);
                 }
+                // superscript
+                '^' if self.peek() == Some('{') => {
+                    push_and_renew!(buffer: String::new(), text);
+                    text.push(self.script_inline(text::InlineType::Superscript)?);
+                }
+                // subscript
+                '~' if self.peek() == Some('{') => {
+                    push_and_renew!(buffer: String::new(), text);
+                    text.push(self.script_inline(text::InlineType::Subscript)?);
+                }
                 // small caps
                 '^' => {
                     push_and_renew!(buffer: String::new(), text);
@@ -659,6 +1215,12 @@ impl<'a> Block<'a> {
                     let kind = text::InlineType::SmallCaps(inner);
                     text.push(self.simple_inline(kind)?);
                 }
+                // code, which is kept exactly as written
+                '`' if self.peek() == Some('`') => {
+                    push_and_renew!(buffer: String::new(), text);
+                    self.idx += 1;
+                    text.push(self.code_inline()?);
+                }
                 // generic `span`
                 '`' => {
                     push_and_renew!(buffer: String::new(), text);
@@ -672,20 +1234,43 @@ impl<'a> Block<'a> {
                     update_multiple!(self, common);
                     text.push(text::Inline { kind, common });
                 }
+                // line continuation, which joins the next line without a space
+                '\\' if self.peek() == Some('\n') => {
+                    self.idx += 1;
+                    if !self.preserve_whitespace {
+                        self.skip_whitespace();
+                    }
+                }
                 // escaped character
                 '\\' => buffer.push(self.expect_escaped()?),
+                // whitespace which is preserved, unless it is at the end of the block
+                c if c.is_whitespace() && self.preserve_whitespace => {
+                    if self.skip_whitespace_virtual() < self.len() {
+                        buffer.push(c);
+                    }
+                }
                 // whitespace (only push one space, regardless of the amount or type of whitespace.
                 c if c.is_whitespace() => {
                     self.skip_whitespace();
                     buffer.push(' ');
+                    collapsed_space = true;
                 }
                 // anything else
                 _ => buffer.push(c),
             }
+            // whitespace removed from the end of an element goes after it, unless there is already
+            // whitespace there
+            if std::mem::take(&mut self.trailing_space)
+                && !self.peek().is_some_and(char::is_whitespace)
+            {
+                buffer.push(' ');
+                collapsed_space = true;
+            }
         }
         if !buffer.is_empty() {
             text.push(buffer);
         }
+        text.normalize();
         Ok(())
     }
 
@@ -695,6 +1280,141 @@ impl<'a> Block<'a> {
         Ok(text::Inline { kind, common })
     }
 
+    /// Parses the text of a directive such as `:phon:a:`, surrounded by the given brackets, in a
+    /// span with the given default class.
+    fn bracketed_inline(&mut self, open: char, close: char, class: &str) -> EResult<text::Inline> {
+        let inner = self.directive()?;
+        let kind = text::InlineType::Span(format!("{}{}{}", open, inner, close).into());
+        let mut common = text::InlineCommon::from(class);
+        update_multiple!(self, common);
+        Ok(text::Inline { kind, common })
+    }
+
+    /// Parses a `:link:` directive: an optional title in braces, which can contain formatting,
+    /// followed by the parameters.
+    ///
+    /// A title in braces takes precedence over a `title` parameter, and the title defaults to the
+    /// URL if neither is given.
+    fn link_inline(&mut self) -> EResult<text::Inline> {
+        let title = if self.peek() == Some('{') {
+            self.idx += 1;
+            let mut title = text::Text::new();
+            self.text_until_char(&mut title, '}')?;
+            Some(title)
+        } else {
+            None
+        };
+        let mut inline = self.simple_inline(text::InlineType::link())?;
+        if let text::InlineType::Link(link) = &mut inline.kind {
+            if let Some(title) = title {
+                link.title = title;
+            }
+            if link.title.0.is_empty() {
+                link.title = link.url.as_str().into();
+            }
+        }
+        Ok(inline)
+    }
+
+    /// Parses the text of a superscript (`^{...}`) or subscript (`~{...}`), and its parameters.
+    fn script_inline(&mut self, kind: fn(text::Text) -> text::InlineType) -> EResult<text::Inline> {
+        self.expect_exact('{')?;
+        let mut inner = text::Text::new();
+        self.text_until_char(&mut inner, '}')?;
+        self.simple_inline(kind(inner))
+    }
+
+    /// Parses the text of a `:fn:{...}` directive, and its parameters.
+    fn footnote_inline(&mut self) -> EResult<text::Inline> {
+        self.expect_exact('{')?;
+        let mut footnote = text::Footnote::default();
+        self.text_until_char(&mut footnote.text, '}')?;
+        self.simple_inline(text::InlineType::Footnote(footnote))
+    }
+
+    /// Parses the text of a `:todo:{...}` directive, and its parameters.
+    fn todo_inline(&mut self) -> EResult<text::Inline> {
+        self.expect_exact('{')?;
+        let mut inner = text::Text::new();
+        self.text_until_char(&mut inner, '}')?;
+        self.simple_inline(text::InlineType::Todo(inner))
+    }
+
+    /// Parses the forms of a `:var-of:{...}{...}` directive, and its parameters.
+    fn variants_inline(&mut self) -> EResult<text::Inline> {
+        let mut forms = Vec::new();
+        self.expect_exact('{')?;
+        loop {
+            let mut form = text::Text::new();
+            self.text_until_char(&mut form, '}')?;
+            forms.push(form);
+            if self.peek() == Some('{') {
+                self.idx += 1;
+            } else {
+                break;
+            }
+        }
+        self.simple_inline(text::InlineType::Variants(forms))
+    }
+
+    /// Parses the text of a `:raw:{...}` directive, which is kept exactly as written, including
+    /// backslashes and balanced pairs of braces.
+    fn verbatim_inline(&mut self) -> EResult<text::Inline> {
+        self.expect_exact('{')?;
+        let mut verbatim = String::new();
+        let mut depth = 0_usize;
+        loop {
+            match self.expect('}')? {
+                '}' if depth == 0 => break,
+                c => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    verbatim.push(c);
+                }
+            }
+        }
+        Ok(text::Inline {
+            kind: text::InlineType::Text(verbatim),
+            common: text::InlineCommon::new(),
+        })
+    }
+
+    /// Parses the text of a ``` ``...`` ``` code element, which is kept exactly as written, and
+    /// its parameters.
+    ///
+    /// The only escape is a backslash before a backtick, so that the closing delimiter can be
+    /// included in the code.
+    fn code_inline(&mut self) -> EResult<text::Inline> {
+        let mut code = String::new();
+        loop {
+            match self.expect('`')? {
+                '`' if self.peek() == Some('`') => {
+                    self.idx += 1;
+                    break;
+                }
+                '\\' if self.peek() == Some('`') => {
+                    self.idx += 1;
+                    code.push('`');
+                }
+                c => code.push(c),
+            }
+        }
+        self.simple_inline(text::InlineType::Code(code))
+    }
+
+    fn comment_inline(&mut self) -> EResult<text::Inline> {
+        self.expect_exact('{')?;
+        let mut comment = String::new();
+        self.bracketed(&mut comment)?;
+        Ok(text::Inline {
+            kind: text::InlineType::Comment(comment),
+            common: text::InlineCommon::new(),
+        })
+    }
+
     fn formatting_inline(
         &mut self,
         delim: char,
@@ -761,6 +1481,12 @@ impl<'a> Block<'a> {
         Err(ErrorKind::Parameter(parameter)).context(ErrorKind::Block(self.start.unwrap()))
     }
 
+    /// Returns the line number of the current position in the block.
+    fn line(&self) -> usize {
+        let consumed = &self.slice[..self.idx.min(self.len())];
+        self.start.unwrap_or(0) + consumed.iter().filter(|&&c| c == '\n').count()
+    }
+
     /// Returns the starting line number of the block, which is only defined for non-empty blocks.
     #[cfg(test)]
     pub fn start(&self) -> Option<usize> {
@@ -774,6 +1500,26 @@ impl<'a> Block<'a> {
         c
     }
 
+    /// Skips the `::` marker at the start of a line, returning an error if it isn't present.
+    fn expect_line_marker(&mut self) -> EResult<()> {
+        if self.slice[self.idx..].starts_with(&[':', ':']) {
+            self.idx += 2;
+            Ok(())
+        } else {
+            Err(ErrorKind::LineMarker(self.line())).context(ErrorKind::Block(self.start.unwrap()))
+        }
+    }
+
+    /// Whether the character at `idx` is preceded only by whitespace on its line, excluding the
+    /// first line of the block.
+    fn at_line_start(&self, idx: usize) -> bool {
+        self.slice[..idx]
+            .iter()
+            .rev()
+            .find(|&&c| c == '\n' || !c.is_whitespace())
+            == Some(&'\n')
+    }
+
     /// Peeks at the next character in the block, without advancing the iterator.
     fn peek(&self) -> Option<char> {
         self.slice.get(self.idx).cloned()
@@ -807,6 +1553,35 @@ impl<'a> Deref for Block<'a> {
     }
 }
 
+/// Parses a parameter list, such as `[id=foo, class=bar]`, in the same way as the parameters of
+/// the built-in directives.
+///
+/// An empty (or all-whitespace) string is parsed as an empty list. Any text after the closing `]`
+/// is an error.
+pub fn parse_parameters(s: &str) -> Result<Vec<Parameter>> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut block = Block::new(&chars, Some(0));
+    let params = block.parameters()?;
+    block.skip_whitespace();
+    if block.idx < block.len() {
+        let rest = block.slice[block.idx..].iter().collect();
+        return Err(ErrorKind::ParameterList(rest).into());
+    }
+    Ok(params)
+}
+
+/// Parses a parameter list, and updates `target` with each parameter in order.
+///
+/// Returns an error for the first parameter `target` doesn't handle.
+pub fn update_parameters(s: &str, target: &mut impl UpdateParam) -> Result<()> {
+    for param in parse_parameters(s)? {
+        if let Some(param) = target.update_param(param)? {
+            return Err(ErrorKind::Parameter(param.0.unwrap_or(param.1)).into());
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::input::Input;
@@ -871,6 +1646,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parameters_escaped_name() {
+        block!(block = r#"[a\=b=c, {d=e}=f, g\,h=i\=j, k\=l]"#);
+        assert_eq!(
+            block.parameters().unwrap(),
+            parameters!["a=b": "c", "d=e": "f", "g,h": "i=j", "k=l"]
+        );
+    }
+
     #[test]
     fn parameters_none() {
         block!(block = "0\n::");
@@ -880,6 +1664,38 @@ mod tests {
         assert!(block.match_hard_line('\n'));
     }
 
+    #[test]
+    fn parse_parameters_public() {
+        assert_eq!(
+            parse_parameters(" [id=foo, {a, b}, class = x  y] ").unwrap(),
+            parameters!["id": "foo", "a, b", "class": "x y"]
+        );
+        assert_eq!(parse_parameters("").unwrap(), parameters![]);
+        let err = parse_parameters("[x] y").unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ParameterList("y".into()));
+    }
+
+    #[test]
+    fn parameters_display() {
+        let params = parse_parameters(r"[id=foo, {a, b}, {x\}y}={ z }]").unwrap();
+        let displayed = params.iter().join(", ");
+        assert_eq!(displayed, r"id=foo, {a, b}, {x\}y}={ z }");
+        assert_eq!(
+            parse_parameters(&format!("[{}]", displayed)).unwrap(),
+            params
+        );
+    }
+
+    #[test]
+    fn update_parameters_public() {
+        let mut common = blocks::BlockCommon::default();
+        update_parameters("[id=foo, bar]", &mut common).unwrap();
+        assert_eq!(common.id, "foo");
+        assert_eq!(common.class, "bar");
+        let err = update_parameters("[title=x]", &mut common).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::Parameter("title".into()));
+    }
+
     #[test]
     fn directive() {
         block!(block = ":foo:x");
@@ -922,12 +1738,206 @@ mod tests {
         assert_eq!(text, text!(Strong("strong"), (" ")))
     }
 
+    #[test]
+    fn text_transcription() {
+        block!(block = r#":phon:a: :phonemic:a: :phone:b:[x]"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(Span("[a]"), (" "), Span("/a/"), (" "), Span("/b/"), (" "));
+        expected.0[0].common.class = "ipa phonetic".into();
+        expected.0[2].common.class = "ipa phonemic".into();
+        expected.0[4].common.class = "x".into();
+        assert_eq!(text, expected)
+    }
+
+    #[test]
+    fn text_merge_adjacent() {
+        block!(block = r#"*a**b* _c_{_d_}"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        assert_eq!(text, text!(Emphasis("ab"), (" "), Italics("cd"), (" ")))
+    }
+
+    #[test]
+    fn text_preserve_whitespace() {
+        block!(block = ":pre: a  *b*\n    c\t d\n");
+        let block = block.parse().unwrap().unwrap();
+        let mut out = Vec::new();
+        block
+            .kind
+            .write(&mut out, &block.common, &Default::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<p id=\"\" class=\"pre \" style=\"white-space: pre-wrap\">\
+            a  <em class=\" \">b</em>\n    c\t d</p>\n\n"
+        );
+    }
+
+    #[test]
+    fn text_line_continuation() {
+        block!(block = "a `lo\\\n  ng`  word\\\nb\\\\\nc");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(("a "), Span("long"), (" wordb\\ c "));
+        expected.0[1].common.class = "conlang".into();
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn text_inline_spaces() {
+        block!(block = "a *b* c");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        assert_eq!(text, text!(("a "), Emphasis("b"), (" c ")));
+
+        block!(block = "a `code` b");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(("a "), Span("code"), (" b "));
+        expected.0[1].common.class = "conlang".into();
+        assert_eq!(text, expected);
+
+        block!(block = "a`code`b");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(("a"), Span("code"), ("b "));
+        expected.0[1].common.class = "conlang".into();
+        assert_eq!(text, expected);
+
+        block!(block = "a\n*b*[x]\n\tc");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(("a "), Emphasis("b"), (" c "));
+        expected.0[1].common.class = "x".into();
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn text_verbatim() {
+        block!(block = r#"a :raw:{*b* _c_ :d:[e] `f` {g} \h ^i^ <&>}  j"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        assert_eq!(text, text!((r#"a *b* _c_ :d:[e] `f` {g} \h ^i^ <&> j "#)));
+
+        let mut out = Vec::new();
+        text.write_inline(&mut out, &Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"a *b* _c_ :d:[e] `f` {g} \h ^i^ &lt;&amp;&gt; j "#
+        );
+
+        block!(block = ":raw:{a {b}");
+        assert!(block.text_rest(&mut text::Text::new()).is_err());
+    }
+
+    #[test]
+    fn text_scripts() {
+        block!(block = r#"C^{0} x~{i}[class=index] ^a^ ~b"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(
+            ("C"),
+            Superscript("0"),
+            (" x"),
+            Subscript("i"),
+            (" "),
+            SmallCaps("a"),
+            (" ~b ")
+        );
+        expected.0[3].common.class = "index".into();
+        assert_eq!(text, expected);
+
+        let mut out = Vec::new();
+        text.write_inline(&mut out, &Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"C<sup class=" ">0</sup> x<sub class=" index">i</sub> <span class="small-caps ">a</span> ~b "#
+        );
+    }
+
+    #[test]
+    fn text_code() {
+        block!(block = r#"a ``:ref:[b] *c* `d` \e <&>``[class=x] f"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(("a "), Code(r#":ref:[b] *c* `d` \e <&>"#), (" f "));
+        expected.0[1].common.class = "x".into();
+        assert_eq!(text, expected);
+
+        let mut out = Vec::new();
+        text.write_inline(&mut out, &Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"a <code class=" x">:ref:[b] *c* `d` \e &lt;&amp;&gt;</code> f "#
+        );
+    }
+
+    #[test]
+    fn text_code_escaped_backticks() {
+        block!(block = r#"``a \`\` b\c``"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        assert_eq!(text, text!(Code(r"a `` b\c"), (" ")));
+    }
+
+    #[test]
+    fn text_trailing_spaces() {
+        // whitespace before a closing delimiter is moved after the element and its parameters
+        block!(block = "a *b\n*c `d `[x] e");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(("a "), Emphasis("b"), (" c "), Span("d"), (" e "));
+        expected.0[3].common.class = "x".into();
+        assert_eq!(text, expected);
+
+        // nested elements
+        block!(block = "*a `b `*c");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(Emphasis("a "), (" c "));
+        if let text::InlineType::Emphasis(inner) = &mut expected.0[0].kind {
+            let mut span = text!(Span("b"));
+            span.0[0].common.class = "conlang".into();
+            inner.0.extend(span.0);
+        }
+        assert_eq!(text, expected);
+
+        // escaped spaces are kept
+        block!(block = r"*a\ * :link:{b }[c]");
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut out = Vec::new();
+        text.write_inline(&mut out, &Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"<em class=" ">a </em> <a class=" " href="c">b</a> "#
+        );
+    }
+
+    #[test]
+    fn text_no_merge_different() {
+        block!(block = r#"*a*_b_*c*[x]*d*"#);
+        let mut text = text::Text::new();
+        block.text_rest(&mut text).unwrap();
+        let mut expected = text!(
+            Emphasis("a"),
+            Italics("b"),
+            Emphasis("c"),
+            Emphasis("d"),
+            (" ")
+        );
+        expected.0[2].common.class = "x".into();
+        assert_eq!(text, expected)
+    }
+
     macro_rules! list {
         ($($text:tt: [$($sl:tt)*]),*) => {
             vec![$(
                 $crate::blocks::list::ListItem {
                     text: $text.into(),
                     sublist: list![$($sl)*],
+                    ..Default::default()
                 },
             )*]
         }
@@ -945,6 +1955,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn list_indent_unit() {
+        let expected = list!["1": ["1a": ["1a-i": []], "1b": []], "2": []];
+        for source in [
+            ":list:[indent=2]\n::1\n  ::1a\n    ::1a-i\n  ::1b\n::2",
+            ":list:[indent=4]\n::1\n    ::1a\n        ::1a-i\n    ::1b\n::2",
+            // indentation which isn't a whole number of levels is rounded down
+            ":list:[indent=4]\n::1\n    ::1a\n         ::1a-i\n     ::1b\n ::2",
+        ] {
+            block!(block = source);
+            let block = block.parse().unwrap().unwrap();
+            assert_eq!(block.kind.as_list().unwrap().items, expected);
+        }
+
+        // without a unit, any deeper indentation starts a sub-list
+        block!(block = ":list:\n::1\n  ::1a\n   ::1a-i");
+        let block = block.parse().unwrap().unwrap();
+        assert_eq!(
+            block.kind.as_list().unwrap().items,
+            list!["1": ["1a": ["1a-i": []]]]
+        );
+        block!(block = ":list:[indent=4]\n::1\n  ::2\n   ::3");
+        let block = block.parse().unwrap().unwrap();
+        assert_eq!(
+            block.kind.as_list().unwrap().items,
+            list!["1": [], "2": [], "3": []]
+        );
+
+        block!(block = ":list:[indent=0]\n::1");
+        assert!(block.parse().is_err());
+    }
+
+    #[test]
+    fn list_item_id() {
+        block!(block = ":list:\n::[id=a]1\n  ::[id=b]1a\n::2");
+        let block = block.parse().unwrap().unwrap();
+        let list = block.kind.as_list().unwrap();
+        assert_eq!(list.items[0].id, "a");
+        assert_eq!(list.items[0].text, "1".into());
+        assert_eq!(list.items[0].sublist[0].id, "b");
+        assert_eq!(list.items[1].id, "");
+
+        // brackets which aren't an `id` parameter are part of the text
+        block!(block = ":list:\n::[a] is a vowel\n::[a] and [b]\n:: [id=c]");
+        let block = block.parse().unwrap().unwrap();
+        let list = block.kind.as_list().unwrap();
+        assert_eq!(
+            list.items,
+            list!["[a] is a vowel": [], "[a] and [b]": [], "[id=c]": []]
+        );
+
+        block!(block = ":list:\n::[id=a, b] Text");
+        assert!(block.parse().is_err());
+    }
+
     #[test]
     fn heading() {
         block!(block = "# Test");
@@ -965,4 +2030,68 @@ mod tests {
             &expected
         );
     }
+
+    #[test]
+    fn gloss_joined_words() {
+        block!(block = ":gloss:\n:: New~York is\n:: New\\~York COP");
+        let mut block = block.parse().unwrap().unwrap();
+        let gloss = block.kind.as_mut_gloss().unwrap();
+        assert_eq!(
+            gloss.gloss[0].words,
+            vec![text!(("New\u{a0}York")), text!(("is"))]
+        );
+        assert_eq!(
+            gloss.gloss[1].words,
+            vec![text!(("New~York")), text!(("COP"))]
+        );
+    }
+
+    #[test]
+    fn table_separator() {
+        block!(block = ":table:[sep=;] Clicks\n;[header];\n::;|a;\\;b\n::;{c;d};e\\|");
+        let mut block = block.parse().unwrap().unwrap();
+        let table = block.kind.as_mut_table().unwrap();
+        assert_eq!(table.columns.len(), 2);
+        let cells = table
+            .rows
+            .iter()
+            .map(|row| row.cells.iter().map(|cell| cell.text.clone()).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            cells,
+            vec![
+                vec![text!(("|a")), text!((";b"))],
+                vec![text!(("c;d")), text!(("e|"))]
+            ]
+        );
+    }
+
+    #[test]
+    fn table_row_missing_marker() {
+        block!(block = ":table: Title\n::|a|b\n|c|d");
+        let err = block.parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::LineMarker(2));
+        assert_eq!(err.location().line, Some(0));
+    }
+
+    #[test]
+    fn gloss_line_after_postamble() {
+        block!(block = ":gloss:\n:: a b\n:: x y\n::[nosplit] comment\n:: c d");
+        let err = block.parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::GlossLine(4));
+    }
+
+    #[test]
+    fn gloss_second_attribution() {
+        block!(block = ":gloss:\n:: a b\n:: x y\n::[attribution] A\n::[attribution] B");
+        let err = block.parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::GlossAttribution(4));
+    }
+
+    #[test]
+    fn quote_second_attribution() {
+        block!(block = ":quote:\n::one\n::[attribution] A\n::[attribution] B");
+        let err = block.parse().unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::QuoteAttribution(3));
+    }
 }
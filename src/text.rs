@@ -1,15 +1,33 @@
-use std::io::{Result as IoResult, Write};
+use std::cmp::Ordering;
+use std::io::{self, Result as IoResult, Write};
+use std::slice;
 
-use crate::blocks::{BlockCommon, BlockType, Parameter, UpdateParam};
+use anyhow::Result as EResult;
+
+use crate::blocks::{
+    control::{Formatting, Undefined},
+    BlockCommon, BlockType, Parameter, UpdateParam,
+};
 use crate::document::Document;
-use crate::errors::Result as EResult;
+use crate::errors::ErrorKind;
 use crate::html;
 
 type OResult<T> = EResult<Option<T>>;
 
 pub trait Referenceable {
-    /// Outputs the text of a reference to the block.
-    fn reference_text(&self) -> Text;
+    /// Outputs the text of a reference to the block, using the reference prefixes defined in the
+    /// document.
+    fn reference_text(&self, document: &Document) -> Text;
+
+    /// Outputs the number of the block, if it is numbered.
+    fn reference_number(&self, _document: &Document) -> Option<String> {
+        None
+    }
+
+    /// The style used for references to the block which don't specify a style.
+    fn default_reference_style(&self) -> ReferenceStyle {
+        ReferenceStyle::Text
+    }
 }
 
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
@@ -33,9 +51,7 @@ impl Text {
     pub fn with_class(self, class: impl Into<String>) -> Text {
         Text(vec![Inline {
             kind: InlineType::Span(self),
-            common: InlineCommon {
-                class: class.into(),
-            },
+            common: InlineCommon::from(class),
         }])
     }
 
@@ -67,6 +83,50 @@ impl Text {
             None => false,
         }
     }
+
+    /// Calls `f` on a mutable reference to each inline element of the text in order, including
+    /// the elements nested inside other elements, which are visited immediately after the element
+    /// containing them.
+    pub fn visit_mut(&mut self, f: &mut dyn FnMut(&mut Inline)) {
+        for inline in &mut self.0 {
+            f(inline);
+            for inner in inline.kind.inner_mut() {
+                inner.visit_mut(f);
+            }
+        }
+    }
+
+    /// Merges adjacent elements of the same kind and with the same parameters (such as two
+    /// adjacent emphasized runs), since they display the same as a single element containing the
+    /// text of both.
+    pub fn normalize(&mut self) {
+        let mut merged = Vec::<Inline>::with_capacity(self.0.len());
+        for inline in self.0.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.common == inline.common => {
+                    if let Err(kind) = last.kind.merge(inline.kind) {
+                        merged.push(Inline {
+                            kind,
+                            common: inline.common,
+                        });
+                    }
+                }
+                _ => merged.push(inline),
+            }
+        }
+        self.0 = merged;
+    }
+
+    /// Calls `f` on each inline element of the text in order, including the elements nested
+    /// inside other elements, which are visited immediately after the element containing them.
+    pub fn visit(&self, f: &mut dyn FnMut(&Inline)) {
+        for inline in &self.0 {
+            f(inline);
+            for inner in inline.kind.inner() {
+                inner.visit(f);
+            }
+        }
+    }
 }
 
 impl BlockType for Text {
@@ -76,6 +136,14 @@ impl BlockType for Text {
         writeln!(w, "</p>\n")?;
         Ok(())
     }
+
+    fn visit_text(&self, f: &mut dyn FnMut(&Text)) {
+        f(self);
+    }
+
+    fn visit_text_mut(&mut self, f: &mut dyn FnMut(&mut Text)) {
+        f(self);
+    }
 }
 
 impl<T> From<T> for Text
@@ -118,12 +186,19 @@ impl From<String> for Inline {
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct InlineCommon {
     pub class: String,
+    /// The URL of an audio file to play when the element is clicked.
+    pub audio: Option<String>,
 }
 
 impl InlineCommon {
     pub fn new() -> InlineCommon {
         Default::default()
     }
+
+    /// Whether the element has the given class.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.class.split_whitespace().any(|c| c == class)
+    }
 }
 
 impl UpdateParam for InlineCommon {
@@ -133,6 +208,10 @@ impl UpdateParam for InlineCommon {
                 self.class = param.1;
                 None
             }
+            Some("audio") => {
+                self.audio = Some(param.1);
+                None
+            }
             _ => Some(param),
         })
     }
@@ -145,6 +224,7 @@ where
     fn from(class: T) -> InlineCommon {
         InlineCommon {
             class: class.into(),
+            audio: None,
         }
     }
 }
@@ -156,10 +236,26 @@ pub enum InlineType {
     Italics(Text),
     Bold(Text),
     SmallCaps(Text),
+    Superscript(Text),
+    Subscript(Text),
     Span(Text),
     Replace(String),
-    Reference(String),
+    Reference(Reference),
+    GlossAbbreviation(String),
     Link(Link),
+    /// A note about something which remains to be done in the document.
+    Todo(Text),
+    /// A note which is written as an HTML comment, so it appears in the source of the output but
+    /// isn't displayed.
+    Comment(String),
+    /// Variant forms of a word, as in "katu ~ kado".
+    Variants(Vec<Text>),
+    /// A field of the document's metadata, such as its title.
+    Metadata(Metadata),
+    /// Code, which is written exactly as given, without any formatting or replacements.
+    Code(String),
+    /// A footnote, which is displayed as a link to its text at the end of the document.
+    Footnote(Footnote),
     Text(String),
 }
 
@@ -172,64 +268,190 @@ impl InlineType {
         InlineType::Reference(Default::default())
     }
 
+    pub fn citation() -> InlineType {
+        InlineType::Reference(Reference {
+            style: Some(ReferenceStyle::Citation),
+            ..Default::default()
+        })
+    }
+
+    pub fn number_reference() -> InlineType {
+        InlineType::Reference(Reference {
+            style: Some(ReferenceStyle::Number),
+            ..Default::default()
+        })
+    }
+
+    pub fn relative_reference(id: String) -> InlineType {
+        InlineType::Reference(Reference {
+            id,
+            style: Some(ReferenceStyle::Relative),
+        })
+    }
+
     fn write(&self, w: &mut dyn Write, common: &InlineCommon, document: &Document) -> IoResult<()> {
-        if let Some(tag) = self.tag() {
-            write!(w, "<{} ", tag)?;
+        // links and references are already links, so they can't link to an audio file.
+        let audio = common
+            .audio
+            .as_ref()
+            .filter(|_| self.tag(document) != Some("a"));
+        if let Some(audio) = audio {
+            write!(w, "<a")?;
+            document.write_class(w, document.default_class("audio"))?;
             write!(
                 w,
-                "class=\"{} {}\"",
-                html::Encoder(self.class()),
-                html::Encoder(&common.class)
+                " href=\"{0}\" data-audio=\"{0}\">",
+                html::Encoder(document.asset_url(audio))
+            )?;
+        }
+        if let Some(tag) = self.tag(document) {
+            write!(w, "<{}", tag)?;
+            document.write_class(
+                w,
+                &format!(
+                    "{} {}",
+                    document.default_class(self.class(document)),
+                    common.class
+                ),
             )?;
             if let InlineType::Link(link) = self {
-                write!(w, " href=\"{}\"", html::Encoder(&link.url))?;
-            } else if let InlineType::Reference(id) = self {
-                write!(w, " href=\"#{}\"", html::Encoder(id))?;
+                write!(
+                    w,
+                    " href=\"{}\"",
+                    html::Encoder(&document.resolve_url(&link.url))
+                )?;
+            } else if let InlineType::Reference(reference) = self {
+                write!(
+                    w,
+                    " href=\"{}\"",
+                    html::Encoder(&document.href(&reference.id))
+                )?;
+            } else if let InlineType::GlossAbbreviation(abbreviation) = self {
+                if let Some(meaning) = document.get_abbreviation(abbreviation) {
+                    write!(w, " title=\"")?;
+                    meaning.write_inline_plain(w, document)?;
+                    write!(w, "\"")?;
+                }
             }
             write!(w, ">")?;
         }
+        // the contents of a link can't contain links to footnotes
+        if audio.is_some() || self.tag(document) == Some("a") {
+            let reference = matches!(self, InlineType::Reference(_));
+            document.in_link(reference, || self.write_contents(w, document))?;
+        } else {
+            self.write_contents(w, document)?;
+        }
+        if let Some(tag) = self.tag(document) {
+            write!(w, "</{}>", tag)?;
+        }
+        if audio.is_some() {
+            write!(w, "</a>")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the contents of the element, without its tag.
+    fn write_contents(&self, w: &mut dyn Write, document: &Document) -> IoResult<()> {
         match self {
             InlineType::Emphasis(t)
             | InlineType::Strong(t)
             | InlineType::Italics(t)
             | InlineType::Bold(t)
             | InlineType::SmallCaps(t)
+            | InlineType::Superscript(t)
+            | InlineType::Subscript(t)
             | InlineType::Span(t)
+            | InlineType::Todo(t)
             | InlineType::Link(Link { title: t, .. }) => t.write_inline(w, document)?,
-            InlineType::Text(s) => write!(w, "{}", html::Encoder(s))?,
-            InlineType::Reference(id) => {
-                if let Some(block) = document.get_id(id) {
-                    if let Some(referenceable) = block.kind.as_referenceable() {
-                        referenceable.reference_text().write_inline(w, document)?;
-                    } else {
-                        write!(
-                            w,
-                            "<span class=\"unreferenceable-block\">#{}</span>",
-                            html::Encoder(id)
-                        )?;
+            InlineType::Text(s) | InlineType::GlossAbbreviation(s) | InlineType::Code(s) => {
+                write!(w, "{}", html::Encoder(s))?
+            }
+            InlineType::Reference(reference) => {
+                let id = &reference.id;
+                if let Some(referenceable) = document.get_referenceable(id) {
+                    match reference.display(referenceable, document)? {
+                        ReferenceDisplay::Citation(number) => {
+                            write!(w, "<sup")?;
+                            document.write_class(w, document.default_class("citation"))?;
+                            write!(w, ">[{}]</sup>", html::Encoder(&number))?;
+                        }
+                        ReferenceDisplay::Number(number) => {
+                            write!(w, "{}", html::Encoder(&number))?
+                        }
+                        ReferenceDisplay::Relative(position) => write!(w, "{}", position)?,
+                        ReferenceDisplay::Text => referenceable
+                            .reference_text(document)
+                            .write_inline(w, document)?,
                     }
+                } else if document.get_id(id).is_some() {
+                    write!(w, "<span")?;
+                    document.write_class(w, document.default_class("unreferenceable-block"))?;
+                    write!(w, ">#{}</span>", html::Encoder(id))?;
                 } else {
-                    write!(
+                    write_undefined(
                         w,
-                        "<span class=\"undefined-reference\">#{}</span>",
-                        html::Encoder(id)
+                        document,
+                        Some("undefined-reference"),
+                        &format!("#{}", id),
+                        ErrorKind::UndefinedReference(id.clone()),
                     )?;
                 }
             }
+            InlineType::Comment(s) => write!(w, "<!-- {} -->", html::CommentEncoder(s))?,
+            // footnotes outside the text of blocks, such as in the title, aren't numbered
+            InlineType::Footnote(Footnote { number: 0, .. }) => write_undefined(
+                w,
+                document,
+                Some("undefined-footnote"),
+                ":fn:",
+                ErrorKind::Footnote,
+            )?,
+            InlineType::Footnote(footnote) => match document.footnote_ids(footnote.number) {
+                Some((id, ref_id)) if !document.writing_link() => write!(
+                    w,
+                    "<a href=\"#{}\" id=\"{}\">{}</a>",
+                    html::Encoder(id),
+                    html::Encoder(ref_id),
+                    footnote.number
+                )?,
+                Some((_, ref_id)) => write!(
+                    w,
+                    "<span id=\"{}\">{}</span>",
+                    html::Encoder(ref_id),
+                    footnote.number
+                )?,
+                None => write!(w, "{}", footnote.number)?,
+            },
+            InlineType::Metadata(field) => {
+                document.with_metadata(*field, |text| text.write_inline(w, document))?
+            }
+            InlineType::Variants(forms) => {
+                for (i, form) in forms.iter().enumerate() {
+                    if i > 0 {
+                        write!(w, " <span")?;
+                        document.write_class(w, document.default_class("variant-separator"))?;
+                        write!(w, ">")?;
+                        write!(w, "{}</span> ", html::Encoder(document.variant_separator()))?;
+                    }
+                    write!(w, "<span")?;
+                    document.write_class(w, document.default_class("variant"))?;
+                    write!(w, ">")?;
+                    form.write_inline(w, document)?;
+                    write!(w, "</span>")?;
+                }
+            }
             InlineType::Replace(key) => match document.get_replacement(key) {
                 Some(t) => t.write_inline(w, document)?,
-                None => {
-                    write!(
-                        w,
-                        "<span class=\"undefined-replace\">:{}:</span>",
-                        html::Encoder(key)
-                    )?;
-                }
+                None => write_undefined(
+                    w,
+                    document,
+                    Some("undefined-replace"),
+                    &format!(":{}:", key),
+                    ErrorKind::UndefinedReplace(key.clone()),
+                )?,
             },
         }
-        if let Some(tag) = self.tag() {
-            write!(w, "</{}>", tag)?;
-        }
         Ok(())
     }
 
@@ -240,52 +462,189 @@ impl InlineType {
             | InlineType::Italics(t)
             | InlineType::Bold(t)
             | InlineType::SmallCaps(t)
+            | InlineType::Superscript(t)
+            | InlineType::Subscript(t)
             | InlineType::Span(t)
+            | InlineType::Todo(t)
             | InlineType::Link(Link { title: t, .. }) => t.write_inline_plain(w, document)?,
-            InlineType::Text(s) => write!(w, "{}", html::Encoder(s))?,
-            InlineType::Reference(id) => {
-                if let Some(block) = document.get_id(id) {
-                    if let Some(referenceable) = block.kind.as_referenceable() {
-                        referenceable
-                            .reference_text()
-                            .write_inline_plain(w, document)?;
-                    } else {
-                        write!(w, "#{}", html::Encoder(id))?;
+            InlineType::Text(s) | InlineType::GlossAbbreviation(s) | InlineType::Code(s) => {
+                write!(w, "{}", html::Encoder(s))?
+            }
+            InlineType::Reference(reference) => {
+                let id = &reference.id;
+                if let Some(referenceable) = document.get_referenceable(id) {
+                    match reference.display(referenceable, document)? {
+                        ReferenceDisplay::Citation(number) => {
+                            write!(w, "[{}]", html::Encoder(&number))?
+                        }
+                        ReferenceDisplay::Number(number) => {
+                            write!(w, "{}", html::Encoder(&number))?
+                        }
+                        ReferenceDisplay::Relative(position) => write!(w, "{}", position)?,
+                        ReferenceDisplay::Text => referenceable
+                            .reference_text(document)
+                            .write_inline_plain(w, document)?,
                     }
-                } else {
+                } else if document.get_id(id).is_some() {
                     write!(w, "#{}", html::Encoder(id))?;
+                } else {
+                    write_undefined(
+                        w,
+                        document,
+                        None,
+                        &format!("#{}", id),
+                        ErrorKind::UndefinedReference(id.clone()),
+                    )?;
+                }
+            }
+            InlineType::Comment(_) | InlineType::Footnote(_) => {}
+            InlineType::Metadata(field) => {
+                document.with_metadata(*field, |text| text.write_inline_plain(w, document))?
+            }
+            InlineType::Variants(forms) => {
+                for (i, form) in forms.iter().enumerate() {
+                    if i > 0 {
+                        write!(w, " {} ", html::Encoder(document.variant_separator()))?;
+                    }
+                    form.write_inline_plain(w, document)?;
                 }
             }
             InlineType::Replace(key) => match document.get_replacement(key) {
                 Some(t) => t.write_inline_plain(w, document)?,
-                None => write!(w, ":{}:", html::Encoder(key))?,
+                None => write_undefined(
+                    w,
+                    document,
+                    None,
+                    &format!(":{}:", key),
+                    ErrorKind::UndefinedReplace(key.clone()),
+                )?,
             },
         }
         Ok(())
     }
 
-    fn tag(&self) -> Option<&'static str> {
+    fn tag(&self, document: &Document) -> Option<&'static str> {
         use self::InlineType::*;
         match self {
+            Emphasis(_) | Strong(_) | Italics(_) | Bold(_)
+                if document.formatting() == Formatting::Classes =>
+            {
+                Some("span")
+            }
             Emphasis(_) => Some("em"),
             Strong(_) => Some("strong"),
             Italics(_) => Some("i"),
             Bold(_) => Some("b"),
+            Superscript(_) => Some("sup"),
+            Subscript(_) => Some("sub"),
+            // undefined references and replacements displayed as text are written on their own
+            Reference(reference)
+                if document.undefined() == Undefined::Text
+                    && document.get_referenceable(&reference.id).is_none()
+                    && document.get_id(&reference.id).is_none() =>
+            {
+                None
+            }
+            Replace(key)
+                if document.undefined() == Undefined::Text
+                    && document.get_replacement(key).is_none() =>
+            {
+                None
+            }
             Link(_) | Reference(_) => Some("a"),
-            Text(_) => None,
+            Todo(_) => Some("mark"),
+            Code(_) => Some("code"),
+            Footnote(footnote) if footnote.number == 0 => None,
+            Footnote(_) => Some("sup"),
+            Comment(_) | Text(_) => None,
             _ => Some("span"),
         }
     }
 
-    fn class(&self) -> &'static str {
+    fn class(&self, document: &Document) -> &'static str {
         use self::InlineType::*;
         match self {
-            SmallCaps(_) => "small-caps",
+            Emphasis(_) | Strong(_) | Italics(_) | Bold(_)
+                if document.formatting() == Formatting::Tags =>
+            {
+                ""
+            }
+            Emphasis(_) => "emphasis",
+            Strong(_) => "strong",
+            Italics(_) => "italics",
+            Bold(_) => "bold",
+            SmallCaps(_) | GlossAbbreviation(_) => "small-caps",
             Reference(_) => "reference",
+            Todo(_) => "todo",
+            Variants(_) => "variants",
+            Footnote(_) => "footnote-ref",
             _ => "",
         }
     }
 
+    /// Appends the contents of `other` to this element, if they are the same kind of formatting or
+    /// both plain text. Otherwise, returns `other`.
+    fn merge(&mut self, other: InlineType) -> Result<(), InlineType> {
+        use self::InlineType::*;
+        match (self, other) {
+            (Emphasis(a), Emphasis(b))
+            | (Strong(a), Strong(b))
+            | (Italics(a), Italics(b))
+            | (Bold(a), Bold(b))
+            | (SmallCaps(a), SmallCaps(b))
+            | (Superscript(a), Superscript(b))
+            | (Subscript(a), Subscript(b))
+            | (Span(a), Span(b)) => {
+                a.0.extend(b.0);
+                a.normalize();
+                Ok(())
+            }
+            (Text(a), Text(b)) => {
+                a.push_str(&b);
+                Ok(())
+            }
+            (_, other) => Err(other),
+        }
+    }
+
+    /// Returns the texts nested inside this element.
+    fn inner(&self) -> &[Text] {
+        match self {
+            InlineType::Emphasis(t)
+            | InlineType::Strong(t)
+            | InlineType::Italics(t)
+            | InlineType::Bold(t)
+            | InlineType::SmallCaps(t)
+            | InlineType::Superscript(t)
+            | InlineType::Subscript(t)
+            | InlineType::Span(t)
+            | InlineType::Todo(t)
+            | InlineType::Link(Link { title: t, .. })
+            | InlineType::Footnote(Footnote { text: t, .. }) => slice::from_ref(t),
+            InlineType::Variants(forms) => forms,
+            _ => &[],
+        }
+    }
+
+    /// Returns mutable references to the texts nested inside this element.
+    fn inner_mut(&mut self) -> &mut [Text] {
+        match self {
+            InlineType::Emphasis(t)
+            | InlineType::Strong(t)
+            | InlineType::Italics(t)
+            | InlineType::Bold(t)
+            | InlineType::SmallCaps(t)
+            | InlineType::Superscript(t)
+            | InlineType::Subscript(t)
+            | InlineType::Span(t)
+            | InlineType::Todo(t)
+            | InlineType::Link(Link { title: t, .. })
+            | InlineType::Footnote(Footnote { text: t, .. }) => slice::from_mut(t),
+            InlineType::Variants(forms) => forms,
+            _ => &mut [],
+        }
+    }
+
     fn starts_with(&self, c: char) -> bool {
         match self {
             InlineType::Emphasis(t)
@@ -293,9 +652,14 @@ impl InlineType {
             | InlineType::Italics(t)
             | InlineType::Bold(t)
             | InlineType::SmallCaps(t)
+            | InlineType::Superscript(t)
+            | InlineType::Subscript(t)
             | InlineType::Span(t)
+            | InlineType::Todo(t)
             | InlineType::Link(Link { title: t, .. }) => t.starts_with(c),
-            InlineType::Text(s) => s.starts_with(c),
+            InlineType::Text(s) | InlineType::GlossAbbreviation(s) | InlineType::Code(s) => {
+                s.starts_with(c)
+            }
             _ => false,
         }
     }
@@ -307,9 +671,14 @@ impl InlineType {
             | InlineType::Italics(t)
             | InlineType::Bold(t)
             | InlineType::SmallCaps(t)
+            | InlineType::Superscript(t)
+            | InlineType::Subscript(t)
             | InlineType::Span(t)
+            | InlineType::Todo(t)
             | InlineType::Link(Link { title: t, .. }) => t.ends_with(c),
-            InlineType::Text(s) => s.ends_with(c),
+            InlineType::Text(s) | InlineType::GlossAbbreviation(s) | InlineType::Code(s) => {
+                s.ends_with(c)
+            }
             _ => false,
         }
     }
@@ -318,15 +687,31 @@ impl InlineType {
 impl UpdateParam for InlineType {
     fn update_param(&mut self, param: Parameter) -> OResult<Parameter> {
         Ok(match *self {
-            InlineType::Reference(ref mut s) => match param.0.as_ref().map(|p| p.as_ref()) {
-                Some("ref") | None => {
-                    *s = param.1;
-                    None
+            InlineType::Reference(ref mut reference) => {
+                match param.0.as_ref().map(|p| p.as_ref()) {
+                    Some("ref") | None => {
+                        reference.id = param.1;
+                        None
+                    }
+                    Some("style") => {
+                        reference.style = Some(match param.1.as_ref() {
+                            "text" => ReferenceStyle::Text,
+                            "cite" => ReferenceStyle::Citation,
+                            "number" => ReferenceStyle::Number,
+                            "relative" => ReferenceStyle::Relative,
+                            _ => {
+                                return Err(
+                                    ErrorKind::ParameterValue(param.0.unwrap(), param.1).into()
+                                )
+                            }
+                        });
+                        None
+                    }
+                    _ => Some(param),
                 }
-                _ => Some(param),
-            },
+            }
             InlineType::Link(ref mut link) => match param.0.as_ref().map(|p| p.as_ref()) {
-                Some("link") | None => {
+                Some("link") | Some("url") | None => {
                     link.url = param.1;
                     None
                 }
@@ -341,8 +726,140 @@ impl UpdateParam for InlineType {
     }
 }
 
+/// Writes the placeholder for an undefined reference or replacement, as specified by the document.
+///
+/// If `class` is `None`, the placeholder is written as plain text regardless of the document's
+/// setting, unless that setting is to raise an error.
+fn write_undefined(
+    w: &mut dyn Write,
+    document: &Document,
+    class: Option<&str>,
+    placeholder: &str,
+    error: ErrorKind,
+) -> IoResult<()> {
+    match (document.undefined(), class) {
+        (Undefined::Error, _) => Err(io::Error::other(error)),
+        (Undefined::Span, Some(class)) => {
+            write!(w, "<span")?;
+            document.write_class(w, document.default_class(class))?;
+            write!(w, ">{}</span>", html::Encoder(placeholder))
+        }
+        (Undefined::Span, None) | (Undefined::Text, _) => {
+            write!(w, "{}", html::Encoder(placeholder))
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Link {
     pub url: String,
     pub title: Text,
 }
+
+impl Link {
+    /// URL schemes which are accepted by `Link::check_url`.
+    const SCHEMES: &'static [&'static str] = &[
+        "http", "https", "ftp", "ftps", "sftp", "mailto", "tel", "sms", "file", "data", "irc",
+        "ircs", "news", "geo", "urn", "doi",
+    ];
+
+    /// Checks whether the URL of the link is well-formed.
+    ///
+    /// Relative URLs, including links to anchors in the document, are always accepted. Absolute
+    /// URLs must parse, and their scheme must be a common one, to catch typos such as `htp://`.
+    pub fn check_url(&self) -> Result<(), ErrorKind> {
+        match url::Url::parse(&self.url) {
+            Ok(url) if Link::SCHEMES.contains(&url.scheme()) => Ok(()),
+            Err(url::ParseError::RelativeUrlWithoutBase) => Ok(()),
+            _ => Err(ErrorKind::LinkUrl(self.url.clone())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Reference {
+    pub id: String,
+    /// The style of the reference, or `None` to use the default style of the referenced block.
+    pub style: Option<ReferenceStyle>,
+}
+
+impl Reference {
+    /// Determines how to display the reference to the given block.
+    ///
+    /// Returns an error if the reference should be displayed as a bare number, but the block is
+    /// not numbered.
+    fn display(
+        &self,
+        referenceable: &dyn Referenceable,
+        document: &Document,
+    ) -> IoResult<ReferenceDisplay> {
+        let style = match self.style {
+            Some(ReferenceStyle::Relative) => match document.relative_position(&self.id) {
+                Some(Ordering::Less) => return Ok(ReferenceDisplay::Relative("above")),
+                Some(Ordering::Greater) => return Ok(ReferenceDisplay::Relative("below")),
+                // the reference is in the block it refers to, or isn't in a block being written,
+                // so fall back to an ordinary reference.
+                _ => None,
+            },
+            style => style,
+        };
+        Ok(
+            match style.unwrap_or_else(|| referenceable.default_reference_style()) {
+                ReferenceStyle::Citation => match referenceable.reference_number(document) {
+                    Some(number) => ReferenceDisplay::Citation(number),
+                    None => ReferenceDisplay::Text,
+                },
+                ReferenceStyle::Number => match referenceable.reference_number(document) {
+                    Some(number) => ReferenceDisplay::Number(number),
+                    None => {
+                        return Err(io::Error::other(ErrorKind::UnnumberedReference(
+                            self.id.clone(),
+                        )))
+                    }
+                },
+                ReferenceStyle::Text | ReferenceStyle::Relative => ReferenceDisplay::Text,
+            },
+        )
+    }
+}
+
+/// A footnote, whose text is displayed at the end of the document.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Footnote {
+    /// The number of the footnote, counting from 1 in the order the footnotes appear in the
+    /// document.
+    pub number: usize,
+    pub text: Text,
+}
+
+/// A field of the document's metadata which can be displayed inline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Metadata {
+    /// The title of the document, as set by `:title:` or `:autotitle:`.
+    Title,
+    /// The author of the document, as set by `:author:`.
+    Author,
+    /// The date the document was last updated, as displayed in the footer.
+    Date,
+}
+
+/// How a reference is displayed, as determined by its style and the block it refers to.
+enum ReferenceDisplay {
+    Text,
+    Citation(String),
+    Number(String),
+    Relative(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReferenceStyle {
+    /// The type and number of the referenced block, as in "table 3".
+    #[default]
+    Text,
+    /// A superscript bracketed number, as in "[3]".
+    Citation,
+    /// Just the number of the referenced block, as in "3".
+    Number,
+    /// Whether the referenced block comes before or after the reference, as in "above".
+    Relative,
+}